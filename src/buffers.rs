@@ -0,0 +1,86 @@
+//! This module implements the allocation of the buffers of a descriptor ring.
+
+use core::cmp::max;
+use core::cmp::min;
+use dma::DmaBuffer;
+use error::E1000Error;
+use kernel::memory;
+use kernel::util::container::vec::Vec;
+use kernel::util::math;
+
+/// The buffers of a descriptor ring.
+///
+/// Buffers are allocated by blocks of pages instead of all at once, so that allocation keeps
+/// working on fragmented memory. Buffers smaller than a page share the same page.
+///
+/// If memory runs out, fewer buffers than requested are allocated.
+pub struct RingBuffers {
+    /// The allocated blocks.
+    blocks: Vec<DmaBuffer>,
+    /// The size of each buffer, in bytes.
+    size: usize,
+    /// The number of buffers in each block.
+    per_block: usize,
+    /// The number of buffers.
+    count: usize,
+}
+
+impl RingBuffers {
+    /// Allocates up to `count` buffers of `size` bytes.
+    ///
+    /// If not a single buffer can be allocated, the function returns an error.
+    pub fn alloc(size: usize, count: usize) -> Result<Self, E1000Error> {
+        let per_block = max(1, memory::PAGE_SIZE / size);
+        let blocks =
+            Vec::with_capacity(math::ceil_div(count, per_block)).map_err(|_| E1000Error::Alloc)?;
+
+        let mut buffs = Self {
+            blocks,
+            size,
+            per_block,
+            count: 0,
+        };
+        while buffs.count < count {
+            let Ok(block) = DmaBuffer::alloc(per_block * size) else {
+                break;
+            };
+            if buffs.blocks.push(block).is_err() {
+                break;
+            }
+            buffs.count = min(count, buffs.count + per_block);
+        }
+
+        if buffs.count == 0 {
+            return Err(E1000Error::Alloc);
+        }
+        Ok(buffs)
+    }
+
+    /// Returns the number of buffers.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Returns the size of each buffer, in bytes.
+    pub fn buff_size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the address for the CPU of the buffer with index `i`.
+    ///
+    /// If out of bounds, the function panics.
+    pub fn get(&self, i: usize) -> *mut u8 {
+        assert!(i < self.count);
+        let block = &self.blocks[i / self.per_block];
+        unsafe { block.as_ptr::<u8>().add((i % self.per_block) * self.size) }
+    }
+
+    /// Returns the address for the device of the buffer with index `i`.
+    ///
+    /// If out of bounds, the function panics.
+    pub fn bus_addr(&self, i: usize) -> u64 {
+        assert!(i < self.count);
+        let block = &self.blocks[i / self.per_block];
+        block.bus_addr() + ((i % self.per_block) * self.size) as u64
+    }
+}
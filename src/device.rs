@@ -0,0 +1,284 @@
+//! This module implements the table of supported devices, along with the description of each
+//! controller family.
+
+/// The type of EEPROM attached to a controller.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EepromType {
+    /// Microwire EEPROM.
+    Microwire,
+    /// SPI EEPROM.
+    Spi,
+    /// Microwire or SPI, depending on the EECD.EE_TYPE bit.
+    Detect,
+}
+
+/// The way the EEPROM Read register (EERD) is laid out, if the family has one.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EerdLayout {
+    /// No EERD register. The EEPROM can only be accessed through EECD.
+    None,
+    /// 8254x layout: done bit at 4, address at bits 8-15.
+    Legacy,
+    /// 8257x layout: done bit at 1, address at bits 2-15.
+    Extended,
+}
+
+/// The descriptor format supported by a controller.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DescFormat {
+    /// Only legacy descriptors are supported.
+    Legacy,
+    /// Both legacy and extended descriptors are supported.
+    Extended,
+}
+
+/// The type of PHY attached to a controller.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PhyType {
+    /// Marvell 88E1000-compatible PHY.
+    M88,
+    /// Intel IGP PHY.
+    Igp,
+    /// Intel BM PHY.
+    Bm,
+    /// No PHY: the link is a fiber/SerDes interface (TBI).
+    Tbi,
+}
+
+/// Quirk: the controller has a TX FIFO that can hang on half-duplex links.
+pub const QUIRK_TX_FIFO_HANG: u32 = 1 << 0;
+/// Quirk: the EEPROM is shared between ports and must be acquired through the SWSM semaphore.
+pub const QUIRK_NVM_SEMAPHORE: u32 = 1 << 1;
+/// Quirk: ASPM must be disabled for the controller to work reliably.
+pub const QUIRK_NO_ASPM: u32 = 1 << 2;
+/// Quirk: a transmit buffer must not cross a 4K boundary on PCI-X.
+pub const QUIRK_PCIX_4K_BOUNDARY: u32 = 1 << 3;
+/// Quirk: a manageability engine may own the PHY and must be asked before resetting it.
+pub const QUIRK_MANAGEABILITY: u32 = 1 << 4;
+/// Quirk: the NVM is a flash, written through EEWR to a shadow RAM that has to be committed.
+pub const QUIRK_NVM_FLASH: u32 = 1 << 5;
+
+/// Description of a family of controllers.
+#[derive(Debug)]
+pub struct Family {
+    /// The name of the family.
+    pub name: &'static str,
+    /// The type of EEPROM.
+    pub eeprom: EepromType,
+    /// The layout of the EEPROM Read register.
+    pub eerd: EerdLayout,
+    /// The descriptor format.
+    pub desc_format: DescFormat,
+    /// Tells whether the controller supports Message Signaled Interrupts.
+    pub msi: bool,
+    /// The type of PHY.
+    pub phy: PhyType,
+    /// Known quirks, as a combination of `QUIRK_*` flags.
+    pub quirks: u32,
+}
+
+impl Family {
+    /// Tells whether the family has the given quirk.
+    pub fn has_quirk(&self, quirk: u32) -> bool {
+        self.quirks & quirk != 0
+    }
+}
+
+/// 82543 family.
+pub static FAMILY_82543: Family = Family {
+    name: "82543",
+    eeprom: EepromType::Microwire,
+    eerd: EerdLayout::None,
+    desc_format: DescFormat::Legacy,
+    msi: false,
+    phy: PhyType::M88,
+    quirks: 0,
+};
+
+/// 82544 family.
+pub static FAMILY_82544: Family = Family {
+    name: "82544",
+    eeprom: EepromType::Microwire,
+    eerd: EerdLayout::None,
+    desc_format: DescFormat::Legacy,
+    msi: false,
+    phy: PhyType::M88,
+    quirks: QUIRK_PCIX_4K_BOUNDARY,
+};
+
+/// 82540/82545/82546 family.
+pub static FAMILY_82540: Family = Family {
+    name: "82540",
+    eeprom: EepromType::Microwire,
+    eerd: EerdLayout::Legacy,
+    desc_format: DescFormat::Legacy,
+    msi: false,
+    phy: PhyType::M88,
+    quirks: 0,
+};
+
+/// 82541 family.
+pub static FAMILY_82541: Family = Family {
+    name: "82541",
+    eeprom: EepromType::Detect,
+    eerd: EerdLayout::Legacy,
+    desc_format: DescFormat::Legacy,
+    msi: false,
+    phy: PhyType::Igp,
+    quirks: 0,
+};
+
+/// 82547 family.
+pub static FAMILY_82547: Family = Family {
+    name: "82547",
+    eeprom: EepromType::Detect,
+    eerd: EerdLayout::Legacy,
+    desc_format: DescFormat::Legacy,
+    msi: false,
+    phy: PhyType::Igp,
+    quirks: QUIRK_TX_FIFO_HANG,
+};
+
+/// 82571/82572 family.
+pub static FAMILY_82571: Family = Family {
+    name: "82571",
+    eeprom: EepromType::Spi,
+    eerd: EerdLayout::Extended,
+    desc_format: DescFormat::Extended,
+    msi: true,
+    phy: PhyType::Igp,
+    quirks: QUIRK_NVM_SEMAPHORE,
+};
+
+/// 82573 family.
+pub static FAMILY_82573: Family = Family {
+    name: "82573",
+    eeprom: EepromType::Spi,
+    eerd: EerdLayout::Extended,
+    desc_format: DescFormat::Extended,
+    msi: true,
+    phy: PhyType::M88,
+    quirks: QUIRK_NVM_SEMAPHORE | QUIRK_NO_ASPM | QUIRK_MANAGEABILITY | QUIRK_NVM_FLASH,
+};
+
+/// 82574 family.
+pub static FAMILY_82574: Family = Family {
+    name: "82574",
+    eeprom: EepromType::Spi,
+    eerd: EerdLayout::Extended,
+    desc_format: DescFormat::Extended,
+    msi: true,
+    phy: PhyType::Bm,
+    quirks: QUIRK_NVM_SEMAPHORE | QUIRK_NO_ASPM | QUIRK_NVM_FLASH,
+};
+
+/// An entry of the table of supported devices.
+#[derive(Debug)]
+pub struct DeviceInfo {
+    /// The PCI device ID.
+    pub id: u16,
+    /// The name of the device.
+    pub name: &'static str,
+    /// The family the device belongs to.
+    pub family: &'static Family,
+    /// Tells whether the device uses a fiber/SerDes interface instead of a copper PHY.
+    pub fiber: bool,
+    /// The number of ports sharing the same controller and EEPROM.
+    ///
+    /// Each port shows up as a separate PCI function. Quad port adapters are made of two dual
+    /// port controllers, each with its own EEPROM.
+    pub ports: u8,
+}
+
+impl DeviceInfo {
+    /// Returns an entry with the default values for the given device.
+    const fn base(id: u16, name: &'static str, family: &'static Family) -> Self {
+        Self {
+            id,
+            name,
+            family,
+            fiber: false,
+            ports: 1,
+        }
+    }
+
+    /// Returns the type of PHY of the device.
+    pub fn phy(&self) -> PhyType {
+        if self.fiber {
+            PhyType::Tbi
+        } else {
+            self.family.phy
+        }
+    }
+}
+
+/// Shorthand to declare an entry of the device table, with optional overrides of the default
+/// values.
+macro_rules! dev {
+    ($id:expr, $name:expr, $family:expr $(, $field:ident: $val:expr)*) => {
+        DeviceInfo {
+            $($field: $val,)*
+            ..DeviceInfo::base($id, $name, &$family)
+        }
+    };
+}
+
+/// The table of supported devices.
+static DEVICES: &[DeviceInfo] = &[
+    dev!(0x1001, "82543GC fiber", FAMILY_82543, fiber: true),
+    dev!(0x1004, "82543GC copper", FAMILY_82543),
+    dev!(0x1008, "82544EI copper", FAMILY_82544),
+    dev!(0x1009, "82544EI fiber", FAMILY_82544, fiber: true),
+    dev!(0x100c, "82544GC copper", FAMILY_82544),
+    dev!(0x100d, "82544GC LOM", FAMILY_82544),
+    dev!(0x100e, "82540EM", FAMILY_82540),
+    dev!(0x1015, "82540EM LOM", FAMILY_82540),
+    dev!(0x1016, "82540EP LOM", FAMILY_82540),
+    dev!(0x1017, "82540EP", FAMILY_82540),
+    dev!(0x101e, "82540EP LP", FAMILY_82540),
+    dev!(0x100f, "82545EM copper", FAMILY_82540),
+    dev!(0x1011, "82545EM fiber", FAMILY_82540, fiber: true),
+    dev!(0x1026, "82545GM copper", FAMILY_82540),
+    dev!(0x1027, "82545GM fiber", FAMILY_82540, fiber: true),
+    dev!(0x1028, "82545GM SerDes", FAMILY_82540, fiber: true),
+    dev!(0x1010, "82546EB copper", FAMILY_82540, ports: 2),
+    dev!(0x1012, "82546EB fiber", FAMILY_82540, fiber: true, ports: 2),
+    dev!(0x101d, "82546EB quad copper", FAMILY_82540, ports: 2),
+    dev!(0x1079, "82546GB copper", FAMILY_82540, ports: 2),
+    dev!(0x107a, "82546GB fiber", FAMILY_82540, fiber: true, ports: 2),
+    dev!(0x107b, "82546GB SerDes", FAMILY_82540, fiber: true, ports: 2),
+    dev!(0x1099, "82546GB quad copper", FAMILY_82540, ports: 2),
+    dev!(0x10b5, "82546GB quad copper KSP3", FAMILY_82540, ports: 2),
+    dev!(0x1013, "82541EI", FAMILY_82541),
+    dev!(0x1018, "82541EI mobile", FAMILY_82541),
+    dev!(0x1076, "82541GI", FAMILY_82541),
+    dev!(0x1077, "82541GI mobile", FAMILY_82541),
+    dev!(0x1078, "82541ER", FAMILY_82541),
+    dev!(0x107c, "82541PI", FAMILY_82541),
+    dev!(0x1019, "82547EI", FAMILY_82547),
+    dev!(0x101a, "82547EI mobile", FAMILY_82547),
+    dev!(0x1075, "82547GI", FAMILY_82547),
+    dev!(0x105e, "82571EB copper", FAMILY_82571, ports: 2),
+    dev!(0x105f, "82571EB fiber", FAMILY_82571, fiber: true, ports: 2),
+    dev!(0x1060, "82571EB SerDes", FAMILY_82571, fiber: true, ports: 2),
+    dev!(0x10a4, "82571EB quad copper", FAMILY_82571, ports: 2),
+    dev!(0x10bc, "82571EB quad copper LP", FAMILY_82571, ports: 2),
+    dev!(0x10d9, "82571EB SerDes dual", FAMILY_82571, fiber: true, ports: 2),
+    dev!(0x10da, "82571EB SerDes quad", FAMILY_82571, fiber: true, ports: 2),
+    dev!(0x107d, "82572EI copper", FAMILY_82571),
+    dev!(0x107e, "82572EI fiber", FAMILY_82571, fiber: true),
+    dev!(0x107f, "82572EI SerDes", FAMILY_82571, fiber: true),
+    dev!(0x10b9, "82572EI", FAMILY_82571),
+    dev!(0x108b, "82573E", FAMILY_82573),
+    dev!(0x108c, "82573E IAMT", FAMILY_82573),
+    dev!(0x109a, "82573L", FAMILY_82573),
+    dev!(0x10d3, "82574L", FAMILY_82574),
+    dev!(0x10f6, "82574LA", FAMILY_82574),
+];
+
+/// Returns the entry of the device table matching the given PCI device ID.
+///
+/// If the device is not supported, the function returns `None`.
+pub fn lookup(device_id: u16) -> Option<&'static DeviceInfo> {
+    DEVICES.iter().find(|d| d.id == device_id)
+}
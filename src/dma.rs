@@ -0,0 +1,54 @@
+//! This module implements memory shared between the CPU and the device through DMA.
+//!
+//! The CPU and the device do not use the same address to access the same memory: the CPU uses a
+//! kernel virtual address, while the device uses a bus address. A [`DmaBuffer`] holds both, so
+//! that addresses of one kind are never used in place of the other.
+
+use error::E1000Error;
+use kernel::memory;
+use kernel::memory::buddy;
+use kernel::memory::buddy::FrameOrder;
+use kernel::util::math;
+
+/// A block of memory accessible by the device through DMA.
+pub struct DmaBuffer {
+    /// The address of the memory for the CPU.
+    ptr: *mut u8,
+    /// The address of the memory for the device.
+    bus_addr: u64,
+    /// The order of the allocated block.
+    order: FrameOrder,
+}
+
+impl DmaBuffer {
+    /// Allocates a buffer of at least `size` bytes. The buffer is aligned on a page boundary.
+    pub fn alloc(size: usize) -> Result<Self, E1000Error> {
+        let order = buddy::get_order(math::ceil_div(size, memory::PAGE_SIZE));
+        let ptr = buddy::alloc_kernel(order).map_err(|_| E1000Error::Alloc)?;
+        // Without an IOMMU, the device accesses physical memory directly
+        // TODO map the buffer through the IOMMU once the kernel supports it
+        let bus_addr = memory::kern_to_phys(ptr) as u64;
+
+        Ok(Self {
+            ptr,
+            bus_addr,
+            order,
+        })
+    }
+
+    /// Returns the address of the buffer for the CPU.
+    pub fn as_ptr<T>(&self) -> *mut T {
+        self.ptr as _
+    }
+
+    /// Returns the address of the buffer for the device.
+    pub fn bus_addr(&self) -> u64 {
+        self.bus_addr
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        buddy::free_kernel(self.ptr, self.order);
+    }
+}
@@ -1,29 +1,91 @@
 //! This module implements the driver structure.
 
 use core::any::Any;
-use core::convert::TryInto;
+use core::convert::TryFrom;
+use device;
+use device::DeviceInfo;
+use error::E1000Error;
+use kernel::device::bus::pci::PCIDevice;
 use kernel::device::bus::pci::PCIManager;
 use kernel::device::driver::Driver;
 use kernel::device::manager;
 use kernel::device::manager::PhysicalDevice;
 use kernel::net;
+use kernel::util::container::string::String;
+use kernel::util::container::vec::Vec;
 use kernel::util::lock::Mutex;
 use kernel::util::ptr::arc::Arc;
+use name::Name;
+use name::NameAllocator;
+use name::Scheme;
 use nic::NIC;
 
 /// Vendor ID for Intel.
 const VENDOR_INTEL: u16 = 0x8086;
-/// Device ID for emulated NICs.
-const DEVICE_EMU: u16 = 0x100e;
-// TODO Add real NICs
+
+/// The naming scheme used for interfaces.
+const NAMING_SCHEME: Scheme = Scheme::Index;
+
+/// The location of a device on the PCI bus.
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub struct PciLocation {
+    /// The bus number.
+    pub bus: u8,
+    /// The device number.
+    pub device: u8,
+    /// The function number.
+    pub function: u8,
+}
+
+impl PciLocation {
+    /// Returns the location of the given device.
+    ///
+    /// If the device is not on the PCI bus, the function returns `None`.
+    pub fn of(dev: &dyn PhysicalDevice) -> Option<Self> {
+        let dev = (dev as &dyn Any).downcast_ref::<PCIDevice>()?;
+        Some(Self {
+            bus: dev.get_bus(),
+            device: dev.get_device(),
+            function: dev.get_function(),
+        })
+    }
+}
+
+/// A NIC handled by the driver.
+struct Entry {
+    /// The location of the NIC's device.
+    location: PciLocation,
+    /// The name under which the NIC is registered in the interfaces list.
+    name: Name,
+    /// The NIC.
+    nic: Arc<Mutex<NIC>>,
+}
+
+impl Entry {
+    /// Stops the NIC and removes it from the interfaces list.
+    fn teardown(self) {
+        net::INTERFACES.lock().remove(&self.name.name);
+        // Other references to the interface may still exist, so the NIC has to be shut down
+        // explicitly instead of relying on it being dropped
+        self.nic.lock().shutdown();
+    }
+}
 
 /// Structure representing the e1000 driver.
-pub struct E1000Driver {}
+pub struct E1000Driver {
+    /// The list of NICs handled by the driver.
+    nics: Mutex<Vec<Entry>>,
+    /// The allocator for interface names.
+    names: NameAllocator,
+}
 
 impl E1000Driver {
     /// Creates a new instance.
     pub fn new() -> Self {
-        let s = Self {};
+        let s = Self {
+            nics: Mutex::new(Vec::new()),
+            names: NameAllocator::new(NAMING_SCHEME),
+        };
 
         let manager = manager::get::<PCIManager>();
         if let Some(manager_mutex) = manager {
@@ -39,6 +101,51 @@ impl E1000Driver {
 
         s
     }
+
+    /// Creates the NIC for the given device, located at `location`, and registers its interface.
+    fn probe(
+        &self,
+        dev: &dyn PhysicalDevice,
+        info: &'static DeviceInfo,
+        location: PciLocation,
+    ) -> Result<(), E1000Error> {
+        let mut nics = self.nics.lock();
+        // Held until the interface is registered, so that no other driver can take its name in
+        // the meantime
+        let mut ifaces = net::INTERFACES.lock();
+
+        let name = self
+            .names
+            .alloc(
+                location,
+                || nics.iter().map(|e| &e.name),
+                |name| ifaces.contains_key(name),
+            )
+            .map_err(|_| E1000Error::Alloc)?;
+        // With the slot scheme, the name may be taken. Check before touching the device, so
+        // that a conflicting name does not reset it
+        if ifaces.contains_key(&name.name) {
+            return Err(E1000Error::NameInUse);
+        }
+        let nic_name = String::try_from(name.name.as_bytes()).map_err(|_| E1000Error::Alloc)?;
+        let iface_name = String::try_from(name.name.as_bytes()).map_err(|_| E1000Error::Alloc)?;
+
+        let nic = NIC::new(dev, info, nic_name)?;
+        let nic = Arc::new(Mutex::new(nic)).map_err(|_| E1000Error::Alloc)?;
+
+        nics.push(Entry {
+            location,
+            name,
+            nic: nic.clone(),
+        })
+        .map_err(|_| E1000Error::Alloc)?;
+        if ifaces.insert(iface_name, nic).is_err() {
+            nics.pop();
+            return Err(E1000Error::Alloc);
+        }
+
+        Ok(())
+    }
 }
 
 impl Driver for E1000Driver {
@@ -51,33 +158,44 @@ impl Driver for E1000Driver {
             return;
         }
 
-        match dev.get_device_id() {
-            // TODO Add real NICs
-            DEVICE_EMU => {
-                // TODO support devices with multiple interfaces
-                match NIC::new(dev) {
-                    Ok(nic) => {
-                        // TODO do not unwrap errors
-                        // TODO figure out how to get the name of the interface
-                        let name = b"TODO".try_into().unwrap();
-                        let iface = Arc::new(Mutex::new(nic)).unwrap();
-
-                        let mut ifaces = net::INTERFACES.lock();
-                        ifaces.insert(name, iface).unwrap();
-                    }
-
-                    Err(e) => {
-                        kernel::println!("e1000 error: {e}");
-                    }
-                }
-            }
+        let Some(info) = device::lookup(dev.get_device_id()) else {
+            return;
+        };
+        let Some(location) = PciLocation::of(dev) else {
+            return;
+        };
 
-            _ => {}
+        // Each port of a multi-port controller is a separate PCI function, and thus gets its own
+        // call to this function
+        if let Err(e) = self.probe(dev, info, location) {
+            kernel::println!(
+                "e1000: {:02x}:{:02x}.{} ({}): probe failed: {e}",
+                location.bus,
+                location.device,
+                location.function,
+                info.name
+            );
         }
     }
 
-    fn on_unplug(&self, _dev: &dyn PhysicalDevice) {
-        // TODO
-        todo!();
+    fn on_unplug(&self, dev: &dyn PhysicalDevice) {
+        let Some(location) = PciLocation::of(dev) else {
+            return;
+        };
+
+        let mut nics = self.nics.lock();
+        let Some(i) = nics.iter().position(|e| e.location == location) else {
+            return;
+        };
+        nics.remove(i).teardown();
+    }
+}
+
+impl Drop for E1000Driver {
+    fn drop(&mut self) {
+        let mut nics = self.nics.lock();
+        while let Some(e) = nics.pop() {
+            e.teardown();
+        }
     }
 }
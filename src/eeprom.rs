@@ -0,0 +1,734 @@
+//! This module implements access to the EEPROM of the NIC.
+//!
+//! Depending on the family, words are read either through the EEPROM Read register (EERD), or by
+//! driving the serial interface of the EEPROM (Microwire or SPI) through the bits of EECD.
+//!
+//! Words are written by driving the serial interface as well, except on controllers whose NVM is a
+//! flash memory. Those are written through the EEPROM Write register (EEWR), which updates a copy
+//! of the NVM in RAM that has to be committed to the flash afterwards.
+
+use core::cmp::min;
+use device::DeviceInfo;
+use device::EepromType;
+use device::EerdLayout;
+use device::QUIRK_NVM_FLASH;
+use device::QUIRK_NVM_SEMAPHORE;
+use error::E1000Error;
+use kernel::device::bar::BAR;
+use kernel::util::container::vec::Vec;
+
+/// The maximum number of attempts to acquire the NVM semaphore.
+const NVM_SEMAPHORE_ATTEMPTS: usize = 100000;
+/// The maximum number of polls of EERD while waiting for a read to complete.
+const EEPROM_READ_ATTEMPTS: usize = 100000;
+/// The maximum number of polls of EECD while waiting for the EEPROM to be granted.
+const EEPROM_GRANT_ATTEMPTS: usize = 100000;
+/// The maximum number of polls of the SPI status register while waiting for the EEPROM to be
+/// ready.
+const SPI_READY_ATTEMPTS: usize = 5000;
+/// The maximum number of polls of a Microwire EEPROM while waiting for a write to complete. Each
+/// poll lasts one half clock period.
+const MICROWIRE_WRITE_ATTEMPTS: usize = 200;
+/// The maximum number of polls of EECD while waiting for the flash to be updated.
+const FLASH_UPDATE_ATTEMPTS: usize = 2000000;
+
+/// The delay for each half clock period on a Microwire EEPROM, in microseconds.
+const MICROWIRE_DELAY: usize = 50;
+/// The delay for each half clock period on a SPI EEPROM, in microseconds.
+const SPI_DELAY: usize = 1;
+
+/// Register address: Device Status
+const REG_STATUS: u16 = 0x8;
+/// Register address: EEPROM/Flash Control & Data
+const REG_EECD: u16 = 0x10;
+/// Register address: EEPROM Read Register
+const REG_EERD: u16 = 0x14;
+/// Register address: EEPROM Write Register. Same layout as the 8257x EERD
+const REG_EEWR: u16 = 0x102c;
+/// Register address: Software Semaphore
+const REG_SWSM: u16 = 0x5b50;
+
+/// EECD flag: Clock input to the EEPROM
+const EECD_SK: u32 = 1 << 0;
+/// EECD flag: Chip select
+const EECD_CS: u32 = 1 << 1;
+/// EECD flag: Data input to the EEPROM
+const EECD_DI: u32 = 1 << 2;
+/// EECD flag: Data output from the EEPROM
+const EECD_DO: u32 = 1 << 3;
+/// EECD flag: Request EEPROM access
+const EECD_REQ: u32 = 1 << 6;
+/// EECD flag: Grant EEPROM access
+const EECD_GNT: u32 = 1 << 7;
+/// EECD flag: EEPROM Present
+const EECD_PRES: u32 = 1 << 8;
+/// EECD flag: EEPROM Size. On Microwire EEPROMs, set for 256 words and clear for 64 words
+const EECD_SIZE: u32 = 1 << 9;
+/// EECD flag: SPI EEPROM addressing. Set for 16 bits addresses and clear for 8 bits addresses
+const EECD_ADDR_BITS: u32 = 1 << 10;
+/// EECD flag: EEPROM Type. Set for SPI and clear for Microwire
+const EECD_TYPE: u32 = 1 << 13;
+/// Offset of the extended size field in EECD (8257x).
+const EECD_SIZE_EX_SHIFT: u32 = 11;
+/// Mask of the extended size field in EECD (8257x), after shifting.
+const EECD_SIZE_EX_MASK: u32 = 0b1111;
+/// EECD flag (8257x): Update the flash with the content of the shadow RAM
+const EECD_FLUPD: u32 = 1 << 23;
+
+/// EERD flag: Start Read
+const EERD_START: u32 = 1 << 0;
+/// EERD flag (8254x layout): Read Done
+const EERD_DONE: u32 = 1 << 4;
+/// EERD flag (8257x layout): Read Done
+const EERD_EXT_DONE: u32 = 1 << 1;
+/// Offset of the address field in EERD (8254x layout).
+const EERD_ADDR_SHIFT: u32 = 8;
+/// Offset of the address field in EERD (8257x layout).
+const EERD_EXT_ADDR_SHIFT: u32 = 2;
+/// Offset of the data field in EERD.
+const EERD_DATA_SHIFT: u32 = 16;
+
+/// SWSM flag: Semaphore bit
+const SWSM_SMBI: u32 = 1 << 0;
+/// SWSM flag: Software EEPROM Semaphore bit
+const SWSM_SWESMBI: u32 = 1 << 1;
+
+/// Microwire opcode: Read
+const MICROWIRE_READ: u32 = 0b110;
+/// Microwire opcode: Write
+const MICROWIRE_WRITE: u32 = 0b101;
+/// Microwire opcode: Erase/Write Enable. The two most significant address bits are part of the
+/// opcode
+const MICROWIRE_EWEN: u32 = 0b10011;
+/// Microwire opcode: Erase/Write Disable. The two most significant address bits are part of the
+/// opcode
+const MICROWIRE_EWDS: u32 = 0b10000;
+/// The size of Microwire opcodes, in bits.
+const MICROWIRE_OPCODE_BITS: u32 = 3;
+
+/// SPI opcode: Read
+const SPI_READ: u32 = 0x03;
+/// SPI opcode: Write
+const SPI_WRITE: u32 = 0x02;
+/// SPI opcode: Set Write Enable Latch
+const SPI_WREN: u32 = 0x06;
+/// SPI opcode: Read Status Register
+const SPI_RDSR: u32 = 0x05;
+/// SPI opcode flag: Ninth address bit, for EEPROMs with 8 bits addresses
+const SPI_A8: u32 = 0x08;
+/// SPI status flag: Write in progress
+const SPI_STATUS_BUSY: u32 = 1 << 0;
+
+/// EEPROM word: configuration, on SPI EEPROMs of the 8254x family.
+const WORD_EEPROM_CFG: u16 = 0x12;
+/// Offset of the size field in the configuration word.
+const EEPROM_CFG_SIZE_SHIFT: u32 = 10;
+/// Mask of the size field in the configuration word, after shifting.
+const EEPROM_CFG_SIZE_MASK: u16 = 0b111;
+/// The base-2 logarithm of the smallest EEPROM size, in words.
+const WORD_SIZE_BASE_SHIFT: u32 = 6;
+/// The base-2 logarithm of the largest supported EEPROM size, in words.
+const WORD_SIZE_MAX_SHIFT: u32 = 14;
+
+/// EEPROM word: Part Number (PBA), high word
+const WORD_PBA_HIGH: u16 = 0x08;
+/// EEPROM word: Part Number (PBA), low word
+const WORD_PBA_LOW: u16 = 0x09;
+/// EEPROM word: Initialization Control Word 1
+const WORD_ICW1: u16 = 0x0a;
+/// EEPROM word: Subsystem ID
+const WORD_SUBSYSTEM_ID: u16 = 0x0b;
+/// EEPROM word: Subsystem Vendor ID
+const WORD_SUBSYSTEM_VENDOR_ID: u16 = 0x0c;
+/// EEPROM word: Device ID
+const WORD_DEVICE_ID: u16 = 0x0d;
+/// EEPROM word: Vendor ID
+const WORD_VENDOR_ID: u16 = 0x0e;
+/// EEPROM word: Initialization Control Word 2
+const WORD_ICW2: u16 = 0x0f;
+/// EEPROM word: Initialization Control Word 3, for the second port
+const WORD_ICW3_PORT_B: u16 = 0x14;
+/// EEPROM word: Initialization Control Word 3, for the first port
+const WORD_ICW3_PORT_A: u16 = 0x24;
+/// EEPROM word: Alternate MAC address pointer
+const WORD_ALT_MAC_PTR: u16 = 0x37;
+
+/// The number of words covered by the checksum. The last of them is the checksum word.
+pub const CHECKSUM_WORDS: u16 = 0x40;
+/// The value the sum of the words covered by the checksum must have.
+pub const CHECKSUM_VALUE: u16 = 0xbaba;
+/// EEPROM word: Checksum
+const WORD_CHECKSUM: u16 = CHECKSUM_WORDS - 1;
+
+/// The method used to read words from the EEPROM.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Backend {
+    /// Through the EEPROM Read register, with the given layout.
+    Eerd(EerdLayout),
+    /// Through EECD, driving a Microwire EEPROM.
+    Microwire,
+    /// Through EECD, driving a SPI EEPROM.
+    Spi,
+}
+
+/// Useful fields decoded from an EEPROM image.
+#[derive(Clone, Copy, Debug)]
+pub struct EepromInfo {
+    /// The Part Number.
+    pub pba: u32,
+    /// Initialization Control Word 1.
+    pub icw1: u16,
+    /// Initialization Control Word 2.
+    pub icw2: u16,
+    /// Initialization Control Word 3, for the port the image has been decoded for.
+    pub icw3: u16,
+    /// The PCI Subsystem ID.
+    pub subsystem_id: u16,
+    /// The PCI Subsystem Vendor ID.
+    pub subsystem_vendor_id: u16,
+    /// The PCI Device ID.
+    pub device_id: u16,
+    /// The PCI Vendor ID.
+    pub vendor_id: u16,
+    /// The address of the alternate MAC addresses, if any.
+    pub alt_mac_ptr: Option<u16>,
+}
+
+impl EepromInfo {
+    /// Decodes the given EEPROM image, for the port with index `port`.
+    ///
+    /// Words that are missing from the image are decoded as zero.
+    pub fn decode(image: &[u16], port: u8) -> Self {
+        let word = |addr: u16| image.get(addr as usize).copied().unwrap_or(0);
+        let icw3 = if port & 1 != 0 {
+            WORD_ICW3_PORT_B
+        } else {
+            WORD_ICW3_PORT_A
+        };
+        let alt_mac_ptr = match word(WORD_ALT_MAC_PTR) {
+            0 | 0xffff => None,
+            ptr => Some(ptr),
+        };
+
+        Self {
+            pba: ((word(WORD_PBA_HIGH) as u32) << 16) | word(WORD_PBA_LOW) as u32,
+            icw1: word(WORD_ICW1),
+            icw2: word(WORD_ICW2),
+            icw3: word(icw3),
+            subsystem_id: word(WORD_SUBSYSTEM_ID),
+            subsystem_vendor_id: word(WORD_SUBSYSTEM_VENDOR_ID),
+            device_id: word(WORD_DEVICE_ID),
+            vendor_id: word(WORD_VENDOR_ID),
+            alt_mac_ptr,
+        }
+    }
+}
+
+/// Returns the size of an EEPROM of `64 << size` words, in words.
+///
+/// The size is capped to the largest supported one, so that a garbage size field cannot make
+/// accesses go past the end of the part.
+fn size_in_words(size: u32) -> usize {
+    1 << min(size + WORD_SIZE_BASE_SHIFT, WORD_SIZE_MAX_SHIFT)
+}
+
+/// The EEPROM of a NIC.
+pub struct Eeprom {
+    /// The BAR0 of the device.
+    bar0: BAR,
+    /// The method used to read words.
+    backend: Backend,
+    /// The serial interface of the EEPROM chip, either `Microwire` or `Spi`.
+    interface: Backend,
+    /// Tells whether the EEPROM is shared with other agents and must be acquired through SWSM.
+    semaphore: bool,
+    /// Tells whether the NVM is a flash, written through EEWR.
+    flash: bool,
+    /// Tells whether access to the EEPROM must be requested through EECD.REQ, which is the case
+    /// from the 82540 onwards.
+    arbitrated: bool,
+    /// The number of address bits on the serial interface.
+    addr_bits: u32,
+    /// The size of the EEPROM, in words.
+    word_size: usize,
+}
+
+impl Eeprom {
+    /// Detects the EEPROM of the device described by `info`, whose registers are mapped by
+    /// `bar0`.
+    ///
+    /// If no EEPROM is present, the function returns `None`.
+    pub fn detect(bar0: &BAR, info: &DeviceInfo) -> Option<Self> {
+        let family = info.family;
+        let eecd = bar0.read::<u32>(REG_EECD as _) as u32;
+        if eecd & EECD_PRES == 0 {
+            return None;
+        }
+
+        let interface = match family.eeprom {
+            EepromType::Microwire => Backend::Microwire,
+            EepromType::Spi => Backend::Spi,
+            EepromType::Detect if eecd & EECD_TYPE != 0 => Backend::Spi,
+            EepromType::Detect => Backend::Microwire,
+        };
+        let backend = match family.eerd {
+            EerdLayout::None => interface,
+            layout => Backend::Eerd(layout),
+        };
+
+        let (addr_bits, word_size) = match interface {
+            // 82543 and 82544 do not report the size of the EEPROM
+            Backend::Microwire if family.eerd == EerdLayout::None => (6, 64),
+            Backend::Microwire if eecd & EECD_SIZE != 0 => (8, 256),
+            Backend::Microwire => (6, 64),
+            _ => {
+                let addr_bits = if eecd & EECD_ADDR_BITS != 0 { 16 } else { 8 };
+                // Updated below when the size is not given by EECD
+                let word_size = if family.eerd == EerdLayout::Extended {
+                    let size = (eecd >> EECD_SIZE_EX_SHIFT) & EECD_SIZE_EX_MASK;
+                    size_in_words(size)
+                } else {
+                    128
+                };
+                (addr_bits, word_size)
+            }
+        };
+
+        let mut eeprom = Self {
+            bar0: bar0.clone(),
+            backend,
+            interface,
+            semaphore: family.has_quirk(QUIRK_NVM_SEMAPHORE),
+            flash: family.has_quirk(QUIRK_NVM_FLASH),
+            // 82543 and 82544 have neither EERD nor the REQ/GNT handshake
+            arbitrated: family.eerd != EerdLayout::None,
+            addr_bits,
+            word_size,
+        };
+        // On the 8254x family, SPI EEPROMs with 16 bits addresses store their size in the
+        // configuration word
+        if interface == Backend::Spi && family.eerd != EerdLayout::Extended && addr_bits == 16 {
+            let cfg = eeprom.read(WORD_EEPROM_CFG).ok()?;
+            let mut size = ((cfg >> EEPROM_CFG_SIZE_SHIFT) & EEPROM_CFG_SIZE_MASK) as u32;
+            // Nonzero sizes are one less than the size exponent. Zero stands for the smallest size
+            if size != 0 {
+                size += 1;
+            }
+            eeprom.word_size = size_in_words(size);
+        }
+
+        Some(eeprom)
+    }
+
+    /// Returns the method used to read words.
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Returns the size of the EEPROM, in words.
+    pub fn word_size(&self) -> usize {
+        self.word_size
+    }
+
+    /// Reads the register at address `addr`.
+    fn read_reg(&self, addr: u16) -> u32 {
+        self.bar0.read::<u32>(addr as _) as _
+    }
+
+    /// Writes the value `val` to the register at address `addr`.
+    fn write_reg(&self, addr: u16, val: u32) {
+        self.bar0.write::<u32>(addr as _, val as _);
+    }
+
+    /// Waits for approximately `us` microseconds.
+    ///
+    /// A register read takes roughly one microsecond.
+    fn delay(&self, us: usize) {
+        for _ in 0..us {
+            self.read_reg(REG_STATUS);
+        }
+    }
+
+    /// Acquires the semaphore protecting the EEPROM shared between the ports of the controller and
+    /// the firmware.
+    fn acquire_semaphore(&self) -> Result<(), E1000Error> {
+        if !self.semaphore {
+            return Ok(());
+        }
+
+        // Reading SWSM sets SMBI if it was clear, granting the semaphore between ports
+        (0..NVM_SEMAPHORE_ATTEMPTS)
+            .find(|_| self.read_reg(REG_SWSM) & SWSM_SMBI == 0)
+            .ok_or(E1000Error::EepromTimeout)?;
+
+        // Then acquire the semaphore between software and firmware
+        let acquired = (0..NVM_SEMAPHORE_ATTEMPTS).any(|_| {
+            self.write_reg(REG_SWSM, self.read_reg(REG_SWSM) | SWSM_SWESMBI);
+            self.read_reg(REG_SWSM) & SWSM_SWESMBI != 0
+        });
+        if !acquired {
+            self.release_semaphore();
+            return Err(E1000Error::EepromTimeout);
+        }
+
+        Ok(())
+    }
+
+    /// Releases the semaphore acquired with [`Self::acquire_semaphore`].
+    fn release_semaphore(&self) {
+        if self.semaphore {
+            let swsm = self.read_reg(REG_SWSM);
+            self.write_reg(REG_SWSM, swsm & !(SWSM_SMBI | SWSM_SWESMBI));
+        }
+    }
+
+    /// Acquires the EEPROM for use through `backend`.
+    fn acquire(&self, backend: Backend) -> Result<(), E1000Error> {
+        self.acquire_semaphore()?;
+        if self.arbitrated {
+            let eecd = self.read_reg(REG_EECD) | EECD_REQ;
+            self.write_reg(REG_EECD, eecd);
+            if matches!(backend, Backend::Eerd(_)) {
+                return Ok(());
+            }
+
+            // Bit-banging requires the access to be granted
+            let granted =
+                (0..EEPROM_GRANT_ATTEMPTS).any(|_| self.read_reg(REG_EECD) & EECD_GNT != 0);
+            if !granted {
+                self.release(backend);
+                return Err(E1000Error::EepromTimeout);
+            }
+        }
+
+        // Select the chip. CS is active high on Microwire and active low on SPI
+        let mut eecd = self.read_reg(REG_EECD) & !(EECD_DI | EECD_SK);
+        match backend {
+            Backend::Spi => eecd &= !EECD_CS,
+            _ => eecd |= EECD_CS,
+        }
+        self.write_reg(REG_EECD, eecd);
+        self.delay(self.clock_delay());
+        Ok(())
+    }
+
+    /// Releases the EEPROM acquired with [`Self::acquire`].
+    fn release(&self, backend: Backend) {
+        let mut eecd = self.read_reg(REG_EECD);
+        match backend {
+            Backend::Microwire => {
+                eecd &= !(EECD_CS | EECD_DI);
+                self.write_reg(REG_EECD, eecd);
+                self.raise_clock(&mut eecd);
+                self.lower_clock(&mut eecd);
+            }
+            Backend::Spi => {
+                eecd = (eecd | EECD_CS) & !EECD_SK;
+                self.write_reg(REG_EECD, eecd);
+                self.delay(self.clock_delay());
+            }
+            Backend::Eerd(_) => {}
+        }
+        if self.arbitrated {
+            self.write_reg(REG_EECD, eecd & !EECD_REQ);
+        }
+        self.release_semaphore();
+    }
+
+    /// Returns the delay for each half clock period on the serial interface, in microseconds.
+    fn clock_delay(&self) -> usize {
+        match self.interface {
+            Backend::Spi => SPI_DELAY,
+            _ => MICROWIRE_DELAY,
+        }
+    }
+
+    /// Raises the clock input of the EEPROM. `eecd` is the current value of EECD.
+    fn raise_clock(&self, eecd: &mut u32) {
+        *eecd |= EECD_SK;
+        self.write_reg(REG_EECD, *eecd);
+        self.delay(self.clock_delay());
+    }
+
+    /// Lowers the clock input of the EEPROM. `eecd` is the current value of EECD.
+    fn lower_clock(&self, eecd: &mut u32) {
+        *eecd &= !EECD_SK;
+        self.write_reg(REG_EECD, *eecd);
+        self.delay(self.clock_delay());
+    }
+
+    /// Shifts the `count` least significant bits of `data` out to the EEPROM, most significant
+    /// bit first.
+    fn shift_out(&self, data: u32, count: u32) {
+        let mut eecd = self.read_reg(REG_EECD) & !EECD_DO;
+        for i in (0..count).rev() {
+            if data & (1 << i) != 0 {
+                eecd |= EECD_DI;
+            } else {
+                eecd &= !EECD_DI;
+            }
+            self.write_reg(REG_EECD, eecd);
+            self.delay(self.clock_delay());
+            self.raise_clock(&mut eecd);
+            self.lower_clock(&mut eecd);
+        }
+        self.write_reg(REG_EECD, eecd & !EECD_DI);
+    }
+
+    /// Shifts `count` bits in from the EEPROM, most significant bit first.
+    fn shift_in(&self, count: u32) -> u32 {
+        let mut eecd = self.read_reg(REG_EECD) & !(EECD_DO | EECD_DI);
+        let mut data = 0;
+        for _ in 0..count {
+            self.raise_clock(&mut eecd);
+            data = (data << 1) | (self.read_reg(REG_EECD) & EECD_DO != 0) as u32;
+            self.lower_clock(&mut eecd);
+        }
+        data
+    }
+
+    /// Deselects then reselects the SPI EEPROM, terminating the current command.
+    fn spi_standby(&self) {
+        let eecd = self.read_reg(REG_EECD);
+        self.write_reg(REG_EECD, eecd | EECD_CS);
+        self.delay(SPI_DELAY);
+        self.write_reg(REG_EECD, eecd & !EECD_CS);
+        self.delay(SPI_DELAY);
+    }
+
+    /// Deselects then reselects the Microwire EEPROM, terminating the current command.
+    fn microwire_standby(&self) {
+        let mut eecd = self.read_reg(REG_EECD) & !(EECD_CS | EECD_SK);
+        self.write_reg(REG_EECD, eecd);
+        self.delay(MICROWIRE_DELAY);
+        self.raise_clock(&mut eecd);
+        eecd |= EECD_CS;
+        self.write_reg(REG_EECD, eecd);
+        self.delay(MICROWIRE_DELAY);
+        self.lower_clock(&mut eecd);
+    }
+
+    /// Waits for the SPI EEPROM to be ready to accept a command.
+    fn spi_wait_ready(&self) -> Result<(), E1000Error> {
+        let ready = (0..SPI_READY_ATTEMPTS).any(|_| {
+            self.shift_out(SPI_RDSR, 8);
+            let status = self.shift_in(8);
+            self.spi_standby();
+            status & SPI_STATUS_BUSY == 0
+        });
+        if ready {
+            Ok(())
+        } else {
+            Err(E1000Error::EepromTimeout)
+        }
+    }
+
+    /// Computes the sum of the words covered by the checksum.
+    ///
+    /// On a valid EEPROM, the result is [`CHECKSUM_VALUE`].
+    pub fn checksum(&self) -> Result<u16, E1000Error> {
+        (0..CHECKSUM_WORDS).try_fold(0u16, |sum, addr| Ok(sum.wrapping_add(self.read(addr)?)))
+    }
+
+    /// Tells whether the checksum of the EEPROM is valid.
+    pub fn is_checksum_valid(&self) -> Result<bool, E1000Error> {
+        Ok(self.checksum()? == CHECKSUM_VALUE)
+    }
+
+    /// Returns the full image of the EEPROM.
+    pub fn dump(&self) -> Result<Vec<u16>, E1000Error> {
+        let mut image = Vec::with_capacity(self.word_size).map_err(|_| E1000Error::Alloc)?;
+        for addr in 0..self.word_size {
+            image
+                .push(self.read(addr as _)?)
+                .map_err(|_| E1000Error::Alloc)?;
+        }
+        Ok(image)
+    }
+
+    /// Reads the word at address `addr`.
+    pub fn read(&self, addr: u16) -> Result<u16, E1000Error> {
+        self.acquire(self.backend)?;
+        let res = match self.backend {
+            Backend::Eerd(layout) => self.read_eerd(layout, addr),
+            Backend::Microwire => Ok(self.read_microwire(addr)),
+            Backend::Spi => self.read_spi(addr),
+        };
+        self.release(self.backend);
+        res
+    }
+
+    /// Writes `words` to the EEPROM starting at address `addr`, then updates the checksum word
+    /// accordingly.
+    ///
+    /// Once written, the words and the checksum are read back to make sure the EEPROM holds them.
+    ///
+    /// The range of words to write must fit in the EEPROM and must not include the checksum word.
+    pub fn write(&self, addr: u16, words: &[u16]) -> Result<(), E1000Error> {
+        let start = addr as usize;
+        let end = start + words.len();
+        if end > self.word_size || (start..end).contains(&(WORD_CHECKSUM as usize)) {
+            return Err(E1000Error::InvalidArgument);
+        }
+
+        self.write_words(addr, words)?;
+        let sum = (0..WORD_CHECKSUM)
+            .try_fold(0u16, |sum, addr| Ok(sum.wrapping_add(self.read(addr)?)))?;
+        self.write_words(WORD_CHECKSUM, &[CHECKSUM_VALUE.wrapping_sub(sum)])?;
+        if self.flash {
+            self.update_flash()?;
+        }
+
+        for (addr, word) in (addr..).zip(words) {
+            if self.read(addr)? != *word {
+                return Err(E1000Error::EepromVerify);
+            }
+        }
+        if !self.is_checksum_valid()? {
+            return Err(E1000Error::EepromVerify);
+        }
+        Ok(())
+    }
+
+    /// Writes `words` starting at address `addr`, without updating the checksum.
+    fn write_words(&self, addr: u16, words: &[u16]) -> Result<(), E1000Error> {
+        let backend = if self.flash {
+            Backend::Eerd(EerdLayout::Extended)
+        } else {
+            self.interface
+        };
+
+        self.acquire(backend)?;
+        let res = match backend {
+            Backend::Eerd(_) => self.write_eewr(addr, words),
+            Backend::Microwire => self.write_microwire(addr, words),
+            Backend::Spi => self.write_spi(addr, words),
+        };
+        self.release(backend);
+        res
+    }
+
+    /// Writes `words` starting at address `addr` through EEWR.
+    fn write_eewr(&self, addr: u16, words: &[u16]) -> Result<(), E1000Error> {
+        for (addr, word) in (addr..).zip(words) {
+            let val = ((*word as u32) << EERD_DATA_SHIFT)
+                | ((addr as u32) << EERD_EXT_ADDR_SHIFT)
+                | EERD_START;
+            self.write_reg(REG_EEWR, val);
+            (0..EEPROM_READ_ATTEMPTS)
+                .find(|_| self.read_reg(REG_EEWR) & EERD_EXT_DONE != 0)
+                .ok_or(E1000Error::EepromTimeout)?;
+        }
+        Ok(())
+    }
+
+    /// Writes `words` starting at address `addr` to a Microwire EEPROM.
+    fn write_microwire(&self, addr: u16, words: &[u16]) -> Result<(), E1000Error> {
+        self.shift_out(MICROWIRE_EWEN, MICROWIRE_OPCODE_BITS + 2);
+        self.shift_out(0, self.addr_bits - 2);
+        self.microwire_standby();
+
+        let res = (addr..).zip(words).try_for_each(|(addr, word)| {
+            self.shift_out(MICROWIRE_WRITE, MICROWIRE_OPCODE_BITS);
+            self.shift_out(addr as _, self.addr_bits);
+            self.shift_out(*word as _, 16);
+
+            // Toggling CS starts the write. DO goes high once it is complete
+            let eecd = self.read_reg(REG_EECD);
+            self.write_reg(REG_EECD, eecd & !EECD_CS);
+            self.delay(MICROWIRE_DELAY);
+            self.write_reg(REG_EECD, eecd | EECD_CS);
+            let done = (0..MICROWIRE_WRITE_ATTEMPTS).any(|_| {
+                self.delay(MICROWIRE_DELAY);
+                self.read_reg(REG_EECD) & EECD_DO != 0
+            });
+            self.microwire_standby();
+            if done {
+                Ok(())
+            } else {
+                Err(E1000Error::EepromTimeout)
+            }
+        });
+
+        self.shift_out(MICROWIRE_EWDS, MICROWIRE_OPCODE_BITS + 2);
+        self.shift_out(0, self.addr_bits - 2);
+        res
+    }
+
+    /// Writes `words` starting at address `addr` to a SPI EEPROM.
+    fn write_spi(&self, addr: u16, words: &[u16]) -> Result<(), E1000Error> {
+        for (addr, word) in (addr..).zip(words) {
+            self.spi_wait_ready()?;
+            self.shift_out(SPI_WREN, 8);
+            self.spi_standby();
+
+            let byte_addr = (addr as u32) * 2;
+            let mut opcode = SPI_WRITE;
+            if self.addr_bits == 8 && byte_addr >= 0x100 {
+                opcode |= SPI_A8;
+            }
+            self.shift_out(opcode, 8);
+            self.shift_out(byte_addr, self.addr_bits);
+            self.shift_out(word.swap_bytes() as _, 16);
+            // Deselecting the chip starts the write
+            self.spi_standby();
+        }
+        self.spi_wait_ready()
+    }
+
+    /// Commits the content of the shadow RAM to the flash.
+    fn update_flash(&self) -> Result<(), E1000Error> {
+        let idle = || {
+            (0..FLASH_UPDATE_ATTEMPTS)
+                .find(|_| self.read_reg(REG_EECD) & EECD_FLUPD == 0)
+                .ok_or(E1000Error::EepromTimeout)
+        };
+
+        // Wait for a previous update to complete
+        idle()?;
+        let eecd = self.read_reg(REG_EECD);
+        self.write_reg(REG_EECD, eecd | EECD_FLUPD);
+        idle()?;
+        Ok(())
+    }
+
+    /// Reads the word at address `addr` through EERD.
+    fn read_eerd(&self, layout: EerdLayout, addr: u16) -> Result<u16, E1000Error> {
+        let (done, addr_shift) = match layout {
+            EerdLayout::Extended => (EERD_EXT_DONE, EERD_EXT_ADDR_SHIFT),
+            _ => (EERD_DONE, EERD_ADDR_SHIFT),
+        };
+
+        self.write_reg(REG_EERD, EERD_START | ((addr as u32) << addr_shift));
+        (0..EEPROM_READ_ATTEMPTS)
+            .map(|_| self.read_reg(REG_EERD))
+            .find(|val| val & done != 0)
+            .map(|val| (val >> EERD_DATA_SHIFT) as u16)
+            .ok_or(E1000Error::EepromTimeout)
+    }
+
+    /// Reads the word at address `addr` from a Microwire EEPROM.
+    fn read_microwire(&self, addr: u16) -> u16 {
+        self.shift_out(MICROWIRE_READ, MICROWIRE_OPCODE_BITS);
+        self.shift_out(addr as _, self.addr_bits);
+        self.shift_in(16) as _
+    }
+
+    /// Reads the word at address `addr` from a SPI EEPROM.
+    fn read_spi(&self, addr: u16) -> Result<u16, E1000Error> {
+        self.spi_wait_ready()?;
+
+        // SPI EEPROMs are addressed in bytes
+        let byte_addr = (addr as u32) * 2;
+        let mut opcode = SPI_READ;
+        if self.addr_bits == 8 && byte_addr >= 0x100 {
+            opcode |= SPI_A8;
+        }
+        self.shift_out(opcode, 8);
+        self.shift_out(byte_addr, self.addr_bits);
+
+        // Bytes come out in address order: the low byte first
+        let data = self.shift_in(16) as u16;
+        Ok(data.swap_bytes())
+    }
+}
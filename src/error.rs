@@ -0,0 +1,86 @@
+//! This module implements the error type of the driver.
+
+use core::fmt;
+use kernel::errno;
+use kernel::errno::Errno;
+
+/// An error occurring while probing or operating a NIC.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum E1000Error {
+    /// The PCI status or command register is not available.
+    MissingPciInfo,
+    /// The BAR0 of the device is missing.
+    MissingBar,
+    /// The device has no interrupt line.
+    MissingIrq,
+    /// A memory allocation failed.
+    Alloc,
+    /// The device has no EEPROM, or the driver cannot access it.
+    MissingEeprom,
+    /// The EEPROM did not respond in time.
+    EepromTimeout,
+    /// The content of the EEPROM does not match what has been written to it.
+    EepromVerify,
+    /// The controller did not complete its reset in time.
+    ResetTimeout,
+    /// The transmitter did not send pending packets in time.
+    TxTimeout,
+    /// The device, or a feature it requires, is not supported by the driver.
+    UnsupportedDevice,
+    /// The name allocated to the interface is already used by another interface.
+    NameInUse,
+    /// An argument passed to the driver is invalid.
+    InvalidArgument,
+    /// The NIC has been shut down, or its device removed.
+    Removed,
+    /// No frame has been received.
+    NoFrame,
+    /// A received frame does not fit in the buffer passed to the driver. The frame is dropped.
+    Truncated,
+    /// A received frame is larger than the maximum frame size. The frame is dropped.
+    Oversize,
+}
+
+impl fmt::Display for E1000Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            Self::MissingPciInfo => "missing PCI status or command register",
+            Self::MissingBar => "missing BAR0",
+            Self::MissingIrq => "missing interrupt line",
+            Self::Alloc => "memory allocation failed",
+            Self::MissingEeprom => "missing EEPROM",
+            Self::EepromTimeout => "EEPROM timeout",
+            Self::EepromVerify => "EEPROM verification failed",
+            Self::ResetTimeout => "reset timeout",
+            Self::TxTimeout => "transmit timeout",
+            Self::UnsupportedDevice => "unsupported device",
+            Self::NameInUse => "interface name already in use",
+            Self::InvalidArgument => "invalid argument",
+            Self::Removed => "device removed",
+            Self::NoFrame => "no frame received",
+            Self::Truncated => "frame truncated",
+            Self::Oversize => "oversize frame",
+        };
+        write!(f, "{msg}")
+    }
+}
+
+impl From<E1000Error> for Errno {
+    fn from(err: E1000Error) -> Self {
+        match err {
+            E1000Error::MissingPciInfo | E1000Error::MissingBar | E1000Error::MissingIrq => {
+                errno!(ENXIO)
+            }
+            E1000Error::Alloc => errno!(ENOMEM),
+            E1000Error::MissingEeprom | E1000Error::EepromVerify => errno!(EIO),
+            E1000Error::EepromTimeout | E1000Error::ResetTimeout | E1000Error::TxTimeout => {
+                errno!(ETIMEDOUT)
+            }
+            E1000Error::UnsupportedDevice | E1000Error::Removed => errno!(ENODEV),
+            E1000Error::NameInUse => errno!(EEXIST),
+            E1000Error::InvalidArgument => errno!(EINVAL),
+            E1000Error::NoFrame => errno!(EAGAIN),
+            E1000Error::Truncated | E1000Error::Oversize => errno!(EMSGSIZE),
+        }
+    }
+}
@@ -5,7 +5,13 @@
 
 extern crate kernel;
 
+mod buffers;
+mod device;
+mod dma;
 mod driver;
+mod eeprom;
+mod error;
+mod name;
 mod nic;
 
 use driver::E1000Driver;
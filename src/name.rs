@@ -0,0 +1,99 @@
+//! This module implements the allocation of interface names.
+//!
+//! Two naming schemes are available:
+//! - Index: interfaces are named `ethN`, where `N` is the smallest index not in use
+//! - Slot: interfaces are named after their location on the PCI bus, such as `enp0s3`
+
+use driver::PciLocation;
+use kernel::errno::Errno;
+use kernel::util::container::string::String;
+
+/// A naming scheme for interfaces.
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum Scheme {
+    /// `ethN` names.
+    Index,
+    /// Names based on the PCI bus, device and function.
+    Slot,
+}
+
+/// An allocated interface name.
+///
+/// The name remains in use as long as this structure is kept in the list passed to
+/// [`NameAllocator::alloc`]. Removing it from the list releases the name.
+pub struct Name {
+    /// The name itself.
+    pub name: String,
+    /// The index allocated for the name, if the index scheme is used.
+    index: Option<u32>,
+}
+
+/// Allocator for interface names.
+pub struct NameAllocator {
+    /// The naming scheme.
+    scheme: Scheme,
+}
+
+impl NameAllocator {
+    /// Creates a new allocator using the given scheme.
+    pub fn new(scheme: Scheme) -> Self {
+        Self {
+            scheme,
+        }
+    }
+
+    /// Allocates a name for the interface of the device at the given location.
+    ///
+    /// `used` returns the names currently allocated by this allocator. `taken` tells whether a
+    /// name is used by another interface, such as one registered by another driver.
+    ///
+    /// With the slot scheme, the name of a device is fixed, so it is returned even if `taken`
+    /// rejects it.
+    pub fn alloc<'n, F, I, T>(
+        &self,
+        location: PciLocation,
+        used: F,
+        taken: T,
+    ) -> Result<Name, Errno>
+    where
+        F: Fn() -> I,
+        I: Iterator<Item = &'n Name>,
+        T: Fn(&String) -> bool,
+    {
+        match self.scheme {
+            Scheme::Index => {
+                let mut index = 0;
+                loop {
+                    if !used().any(|n| n.index == Some(index)) {
+                        let name = kernel::format!("eth{index}")?;
+                        if !taken(&name) {
+                            return Ok(Name {
+                                name,
+                                index: Some(index),
+                            });
+                        }
+                    }
+                    index += 1;
+                }
+            }
+
+            Scheme::Slot => {
+                let PciLocation {
+                    bus,
+                    device,
+                    function,
+                } = location;
+                let name = if function == 0 {
+                    kernel::format!("enp{bus}s{device}")?
+                } else {
+                    kernel::format!("enp{bus}s{device}f{function}")?
+                };
+
+                Ok(Name {
+                    name,
+                    index: None,
+                })
+            }
+        }
+    }
+}
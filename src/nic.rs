@@ -1,35 +1,94 @@
 //! This module implements the NIC structure, representing an e1000-compatible NIC.
 
+use buffers::RingBuffers;
 use core::cmp::min;
+use core::fmt;
+use core::mem;
 use core::mem::size_of;
 use core::ptr;
 use core::slice;
+use core::sync::atomic;
+use core::sync::atomic::AtomicU32;
+use core::sync::atomic::Ordering;
+use device::DeviceInfo;
+use device::PhyType;
+use device::QUIRK_MANAGEABILITY;
+use dma::DmaBuffer;
+use driver::PciLocation;
+use eeprom::Eeprom;
+use eeprom::EepromInfo;
+use error::E1000Error;
 use kernel::device::bar::BAR;
+use kernel::device::bus::pci;
 use kernel::device::manager::PhysicalDevice;
+use kernel::errno;
 use kernel::errno::Errno;
 use kernel::event;
 use kernel::event::CallbackHook;
-use kernel::memory;
-use kernel::memory::buddy;
+use kernel::event::CallbackResult;
 use kernel::net;
 use kernel::net::buff::BuffList;
 use kernel::net::BindAddress;
 use kernel::net::MAC;
+use kernel::util::container::string::String;
+use kernel::util::container::vec::Vec;
 use kernel::util::math;
-
-/// The number of receive descriptors.
-const RX_DESC_COUNT: usize = 128;
-/// The size of a receive descriptor's buffer.
-const RX_BUFF_SIZE: usize = 16384;
-/// The number of transmit descriptors.
-const TX_DESC_COUNT: usize = 128;
-/// The size of a transmit descriptor's buffer.
-const TX_BUFF_SIZE: usize = 16384;
-
-/// Register address: EEPROM/Flash Control & Data
-const REG_EECD: u16 = 0x10;
-/// Register address: EEPROM Read Register
-const REG_EERD: u16 = 0x14;
+use kernel::util::ptr::arc::Arc;
+
+/// The maximum number of descriptors in a ring.
+const MAX_DESC_COUNT: usize = 4096;
+// TODO choose the ring sizes at probe time from the arguments of the module, once the kernel
+// can pass arguments to modules. Until then, rings are resized with `NIC::resize_rings`
+/// The requested number of descriptors in the receive ring of a newly probed NIC.
+const DEFAULT_RX_DESCS: usize = 128;
+/// The requested number of descriptors in the transmit ring of a newly probed NIC.
+const DEFAULT_TX_DESCS: usize = 128;
+/// The default MTU.
+const DEFAULT_MTU: usize = 1500;
+/// The minimum MTU.
+const MIN_MTU: usize = 68;
+/// The maximum MTU, limited by the size of the largest receive buffer.
+const MAX_MTU: usize = 16110;
+/// The length of the Ethernet header.
+const ETH_HEADER_LEN: usize = 14;
+/// The length of an 802.1Q tag.
+const VLAN_TAG_LEN: usize = 4;
+/// The length of the Frame Check Sequence.
+const FCS_LEN: usize = 4;
+/// The maximum length of a frame which is not a long packet, including the FCS.
+const MAX_STD_FRAME_LEN: usize = 1522;
+/// The size of a transmit descriptor's buffer. Larger packets span several descriptors.
+const TX_BUFF_SIZE: usize = 2048;
+/// The number of descriptors in a ring must be a multiple of this value, for the length of the
+/// ring to be a multiple of 128 bytes.
+const RING_GRANULARITY: usize = 8;
+
+/// The default maximum number of multicast groups filtered through the Multicast Table Array.
+/// Beyond this number, all multicast packets are accepted.
+const DEFAULT_MULTICAST_LIMIT: usize = 128;
+
+/// The maximum number of polls of the transmit ring while waiting for it to drain.
+const TX_DRAIN_ATTEMPTS: usize = 100000;
+/// The maximum number of polls of CTRL while waiting for a reset to complete.
+const RESET_ATTEMPTS: usize = 100000;
+/// The number of register reads used as a delay while the PHY is held in reset.
+///
+/// A register read takes roughly one microsecond, which gives the 10 ms the PHY requires.
+const PHY_RESET_DELAY: usize = 10000;
+
+/// Offset of the command/status dword in the PCI configuration space, in dwords.
+const PCI_COMMAND_OFF: u8 = 1;
+/// PCI command flag: Memory Space Enable
+const PCI_COMMAND_MEMORY: u16 = 1 << 1;
+/// PCI command flag: Bus Master Enable
+const PCI_COMMAND_MASTER: u16 = 1 << 2;
+
+/// Register address: Device Control
+const REG_CTRL: u16 = 0x0;
+/// Register address: Device Status
+const REG_STATUS: u16 = 0x8;
+/// Register address: VLAN Ether Type
+const REG_VET: u16 = 0x38;
 
 /// Register address: Interrupt Cause Read Register
 const REG_ICR: u16 = 0xc0;
@@ -37,6 +96,8 @@ const REG_ICR: u16 = 0xc0;
 const REG_ITR: u16 = 0xc4;
 /// Register address: Interrupt Mask Set/Read Register
 const REG_IMS: u16 = 0xd0;
+/// Register address: Interrupt Mask Clear Register
+const REG_IMC: u16 = 0xd8;
 
 /// Register address: Receive Control
 const REG_RCTL: u16 = 0x100;
@@ -56,6 +117,42 @@ const REG_RDH: u16 = 0x2810;
 /// Register address: Receive Descriptor Tail
 const REG_RDT: u16 = 0x2818;
 
+/// Register address: first entry of the VLAN Filter Table Array
+const REG_VFTA: u16 = 0x5600;
+/// The number of entries in the VLAN Filter Table Array.
+const VFTA_COUNT: u16 = 128;
+
+/// The Ethertype identifying 802.1Q tagged packets.
+const ETHERTYPE_VLAN: u32 = 0x8100;
+/// Mask of the VLAN ID in a tag.
+const VLAN_VID_MASK: u16 = 0xfff;
+
+/// Register address: Management Control
+const REG_MANC: u16 = 0x5820;
+
+/// Register address: Receive Address Low (first entry)
+const REG_RAL0: u16 = 0x5400;
+/// Register address: Receive Address High (first entry)
+const REG_RAH0: u16 = 0x5404;
+/// The number of Receive Address registers. The first one holds the address of the NIC.
+const RA_COUNT: u16 = 16;
+
+/// Register address: first entry of the Multicast Table Array
+const REG_MTA: u16 = 0x5200;
+/// The number of entries in the Multicast Table Array.
+const MTA_COUNT: u16 = 128;
+/// The bits of the address used to hash into the Multicast Table Array, as a value of RCTL.MO:
+/// - `0b00`: bits 47:36
+/// - `0b01`: bits 46:35
+/// - `0b10`: bits 45:34
+/// - `0b11`: bits 43:32
+const MULTICAST_OFFSET: u32 = 0b00;
+
+/// Register address: first statistics register
+const REG_STATS_BEGIN: u16 = 0x4000;
+/// Register address: end of the statistics registers (exclusive)
+const REG_STATS_END: u16 = 0x4100;
+
 /// Register address: Transmit Descriptor Address Low
 const REG_TDBAL: u16 = 0x3800;
 /// Register address: Transmit Descriptor Address High
@@ -67,20 +164,66 @@ const REG_TDH: u16 = 0x3810;
 /// Register address: Transmit Descriptor Tail
 const REG_TDT: u16 = 0x3818;
 
-/// Interrupt Mask Set flag: Transmit Descriptor Written Back
-const IMS_TXDW: u32 = 1 << 0;
-/// Interrupt Mask Set flag: Transmit Queue Empty
-const IMS_TXQE: u32 = 1 << 1;
+/// CTRL flag: Link Reset
+const CTRL_LRST: u32 = 1 << 3;
+/// CTRL flag: Auto-Speed Detection Enable
+const CTRL_ASDE: u32 = 1 << 5;
+/// CTRL flag: Set Link Up
+const CTRL_SLU: u32 = 1 << 6;
+/// CTRL flag: Invert Loss-of-Signal
+const CTRL_ILOS: u32 = 1 << 7;
+/// CTRL flag: Force Speed
+const CTRL_FRCSPD: u32 = 1 << 11;
+/// CTRL flag: Force Duplex
+const CTRL_FRCDPLX: u32 = 1 << 12;
+/// CTRL flag: VLAN Mode Enable
+const CTRL_VME: u32 = 1 << 30;
+/// CTRL flag: Device Reset
+const CTRL_RST: u32 = 1 << 26;
+/// CTRL flag: PHY Reset
+const CTRL_PHY_RST: u32 = 1 << 31;
+
+/// RAH flag: Address Valid
+const RAH_AV: u32 = 1 << 31;
+
+/// MANC flag: Block PHY Reset
+const MANC_BLK_PHY_RST: u32 = 1 << 18;
+
+/// STATUS flag: Full Duplex
+const STATUS_FD: u32 = 1 << 0;
+/// STATUS flag: Link Up
+const STATUS_LU: u32 = 1 << 1;
+/// Offset of the speed field in STATUS.
+const STATUS_SPEED_SHIFT: u32 = 6;
+/// Mask of the speed field in STATUS, after shifting.
+const STATUS_SPEED_MASK: u32 = 0b11;
+
+/// Offset of the LAN ID field in STATUS: the index of the port on multi-port controllers.
+const STATUS_LAN_ID_SHIFT: u32 = 2;
+/// Mask of the LAN ID field in STATUS, after shifting.
+const STATUS_LAN_ID_MASK: u32 = 0b11;
+
+// The flags of the Interrupt Cause Read register are the same as the ones of the Interrupt Mask
+// Set register
+
 /// Interrupt Mask Set flag: Link Status Change
 const IMS_LSC: u32 = 1 << 2;
 /// Interrupt Mask Set flag: Receive Sequence Error
 const IMS_RXSEQ: u32 = 1 << 3;
-/// Interrupt Mask Set flag: Receive Descriptor Minimum Threshold hit
-const IMS_RXDMT0: u32 = 1 << 4;
 /// Interrupt Mask Set flag: Receiver FIFO Overrun
 const IMS_RXO: u32 = 1 << 6;
-/// Interrupt Mask Set flag: Receiver Timer Interrupt
-const IMS_RTX0: u32 = 1 << 7;
+
+/// Interrupt causes related to reception.
+///
+/// Received frames are polled from the ring, so the receive timer and descriptor threshold
+/// causes are left masked until something waits for them.
+const INT_RX: u32 = IMS_RXO;
+/// Interrupt causes related to errors.
+const INT_ERR: u32 = IMS_RXSEQ;
+/// Interrupt causes enabled on the NIC.
+///
+/// Transmit descriptors are reclaimed when transmitting, so transmission causes are left masked.
+const INT_MASK: u32 = INT_RX | IMS_LSC | INT_ERR;
 
 /// RCTL flag: Receiver Enable
 const RCTL_EN: u32 = 1 << 1;
@@ -92,6 +235,10 @@ const RCTL_UPE: u32 = 1 << 3;
 const RCTL_MPE: u32 = 1 << 4;
 /// RCTL flag: Long Packet Reception Enable
 const RCTL_LPE: u32 = 1 << 5;
+/// Offset of the Multicast Offset field in RCTL.
+const RCTL_MO_SHIFT: u32 = 12;
+/// Offset of the Receive Buffer Size field in RCTL.
+const RCTL_BSIZE_SHIFT: u32 = 16;
 /// RCTL flag: Broadcast Accept Mode
 const RCTL_BAM: u32 = 1 << 15;
 /// RCTL flag: VLAN Filter Enable
@@ -128,6 +275,8 @@ const RX_STA_EOP: u8 = 1 << 1;
 const RX_STA_IXSM: u8 = 1 << 2;
 /// Receive descriptor status flag: Packet is 802.1Q
 const RX_STA_VP: u8 = 1 << 3;
+/// Receive descriptor status flag: UDP Checksum Calculated on Packet (8257x)
+const RX_STA_UDPCS: u8 = 1 << 4;
 /// Receive descriptor status flag: TCP Checksum Calculated on Packet
 const RX_STA_TCPCS: u8 = 1 << 5;
 /// Receive descriptor status flag: IP Checksum Calculated on Packet
@@ -135,6 +284,24 @@ const RX_STA_IPCS: u8 = 1 << 6;
 /// Receive descriptor status flag: Passed in-exact filter
 const RX_STA_PIF: u8 = 1 << 7;
 
+/// Receive descriptor error flag: CRC Error or Alignment Error
+pub const RX_ERR_CE: u8 = 1 << 0;
+/// Receive descriptor error flag: Symbol Error
+pub const RX_ERR_SE: u8 = 1 << 1;
+/// Receive descriptor error flag: Sequence Error
+pub const RX_ERR_SEQ: u8 = 1 << 2;
+/// Receive descriptor error flag: Carrier Extension Error
+pub const RX_ERR_CXE: u8 = 1 << 4;
+/// Receive descriptor error flag: TCP/UDP Checksum Error
+pub const RX_ERR_TCPE: u8 = 1 << 5;
+/// Receive descriptor error flag: IP Checksum Error
+pub const RX_ERR_IPE: u8 = 1 << 6;
+/// Receive descriptor error flag: RX Data Error
+pub const RX_ERR_RXE: u8 = 1 << 7;
+/// Receive descriptor error flags making a frame bad. Checksum errors do not, since the frame
+/// itself has been received correctly.
+const RX_ERR_FRAME: u8 = RX_ERR_CE | RX_ERR_SE | RX_ERR_SEQ | RX_ERR_CXE | RX_ERR_RXE;
+
 /// Transmit descriptor command flag: End of Packet
 const TX_CMD_EOP: u8 = 0x01;
 /// Transmit descriptor command flag: Insertion of FCS
@@ -159,11 +326,44 @@ const TX_STA_LC: u8 = 1 << 2;
 /// Transmit descriptor status flag: Transmit Underrun
 const TX_STA_TU: u8 = 1 << 3;
 
-// TODO caches need to be flushed before reading/writing from/to receive/transmit buffers
+// DMA is cache coherent, so buffers and descriptors do not need to be flushed. However, the NIC
+// accesses descriptors concurrently with the CPU, so they are only accessed through volatile
+// operations, and barriers keep these accesses ordered with respect to the Descriptor Done bit and
+// to writes to tail registers.
+
+/// Reads the field `$field` of the descriptor pointed to by `$desc`, with a volatile read.
+macro_rules! desc_read {
+    ($desc:expr, $field:ident) => {
+        unsafe { ptr::addr_of!((*$desc).$field).read_volatile() }
+    };
+}
+
+/// Writes `$val` to the field `$field` of the descriptor pointed to by `$desc`, with a volatile
+/// write.
+macro_rules! desc_write {
+    ($desc:expr, $field:ident, $val:expr) => {
+        unsafe { ptr::addr_of_mut!((*$desc).$field).write_volatile($val) }
+    };
+}
+
+/// Read barrier: descriptor fields read after the barrier are not read before the Descriptor
+/// Done bit checked before it.
+fn rmb() {
+    atomic::fence(Ordering::Acquire);
+}
+
+/// Write barrier: descriptors written before the barrier are visible to the NIC before it is told
+/// about them through a tail register written after it.
+fn wmb() {
+    atomic::fence(Ordering::SeqCst);
+}
 
 /// The receive descriptor.
+///
+/// The layout has no padding, and descriptors are aligned on 16 bytes in the ring, so the fields
+/// are naturally aligned.
 #[derive(Default)]
-#[repr(packed)]
+#[repr(C)]
 struct RXDesc {
     /// The physical address of the data.
     addr: u64,
@@ -175,14 +375,16 @@ struct RXDesc {
     status: u8,
     /// Error flags.
     errors: u8,
-    /// TODO doc
+    /// The 802.1Q tag of the packet, if [`RX_STA_VP`] is set. The tag is stripped from the data.
     special: u16,
 }
 
 // TODO: This is the legacy structure. Add support for the new version
 /// The transmit descriptor.
+///
+/// Like [`RXDesc`], the layout has no padding.
 #[derive(Default)]
-#[repr(packed)]
+#[repr(C)]
 struct TXDesc {
     /// The physical address of the data.
     addr: u64,
@@ -196,93 +398,468 @@ struct TXDesc {
     status: u8,
     /// CheckSum Start: the offset at which computation of the checksum starts in the given data.
     css: u8,
-    /// TODO doc
+    /// The 802.1Q tag to insert in the packet, if [`TX_CMD_VLE`] is set.
     special: u16,
 }
 
+/// Tells whether the given MAC address can be used as the address of an interface.
+///
+/// Multicast and all-zero addresses are invalid.
+fn is_valid_mac(mac: &MAC) -> bool {
+    mac[0] & 1 == 0 && mac.iter().any(|b| *b != 0)
+}
+
+/// Returns the ring size to use when `requested` descriptors are requested, within the hardware
+/// constraints.
+fn ring_size(requested: usize) -> usize {
+    let size = requested.clamp(RING_GRANULARITY, MAX_DESC_COUNT);
+    size - size % RING_GRANULARITY
+}
+
+/// Sets the given flags in the PCI command register of the device at `location`, leaving the
+/// other flags untouched.
+///
+/// The function returns the new value of the command register.
+fn enable_command(location: PciLocation, flags: u16) -> u16 {
+    let val = pci::read_long(
+        location.bus,
+        location.device,
+        location.function,
+        PCI_COMMAND_OFF,
+    );
+    let command_reg = val as u16 | flags;
+    // The status register shares the dword. Its bits are either read-only or cleared by writing
+    // one, so zeros are written to leave it untouched
+    pci::write_long(
+        location.bus,
+        location.device,
+        location.function,
+        PCI_COMMAND_OFF,
+        command_reg as u32,
+    );
+    command_reg
+}
+
+/// Allocates a ring of `count` descriptors of type `T`.
+fn alloc_descs<T>(count: usize) -> Result<DmaBuffer, E1000Error> {
+    DmaBuffer::alloc(count * size_of::<T>())
+}
+
+/// Returns the length of the largest frame that can be received with the given MTU, including a
+/// VLAN tag and the FCS.
+fn max_frame_len(mtu: usize) -> usize {
+    mtu + ETH_HEADER_LEN + VLAN_TAG_LEN + FCS_LEN
+}
+
+/// Returns the size of the receive buffers for the given MTU, among the sizes supported by RCTL.
+fn rx_buff_size(mtu: usize) -> usize {
+    max_frame_len(mtu).next_power_of_two().max(2048)
+}
+
+/// Returns the RCTL flags selecting the given receive buffer size.
+fn rctl_buff_size(size: usize) -> u32 {
+    match size {
+        2048 => 0b00 << RCTL_BSIZE_SHIFT,
+        4096 => RCTL_BSEX | (0b11 << RCTL_BSIZE_SHIFT),
+        8192 => RCTL_BSEX | (0b10 << RCTL_BSIZE_SHIFT),
+        _ => RCTL_BSEX | (0b01 << RCTL_BSIZE_SHIFT),
+    }
+}
+
+/// Returns the 12 bits hash of the given multicast address, selecting a bit in the Multicast Table
+/// Array.
+fn mta_hash(mac: &MAC) -> u16 {
+    let hi = ((mac[5] as u16) << 8) | mac[4] as u16;
+    let shift = match MULTICAST_OFFSET {
+        0b00 => 4,
+        0b01 => 3,
+        0b10 => 2,
+        _ => 0,
+    };
+    (hi >> shift) & 0xfff
+}
+
+/// The number of random MAC addresses generated so far.
+static RANDOM_MACS: AtomicU32 = AtomicU32::new(0);
+
+/// Generates a random locally administered unicast MAC address.
+///
+/// `salt` is mixed with the timestamp counter and the number of addresses generated so far, so
+/// that NICs probed at the same time get different addresses. Where there is no timestamp
+/// counter, the address of the stack is used instead.
+fn random_mac(salt: usize) -> MAC {
+    #[cfg(target_arch = "x86")]
+    use core::arch::x86::_rdtsc;
+    #[cfg(target_arch = "x86_64")]
+    use core::arch::x86_64::_rdtsc;
+
+    // TODO use the kernel's entropy source when available
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    let seed = unsafe { _rdtsc() };
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+    let seed = &salt as *const usize as u64;
+    let count = RANDOM_MACS.fetch_add(1, Ordering::Relaxed) as u64;
+
+    // splitmix64 finalizer
+    let mut x = seed ^ (salt as u64).rotate_left(32) ^ count.rotate_left(48);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^= x >> 31;
+
+    let mut mac = [0; 6];
+    mac.copy_from_slice(&x.to_le_bytes()[..6]);
+    // Clear the multicast bit and set the locally administered bit
+    mac[0] = (mac[0] & !0b01) | 0b10;
+    mac
+}
+
+/// The state of an established link.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Link {
+    /// The negotiated speed, in Mb/s.
+    pub speed: u32,
+    /// Tells whether the link is full duplex.
+    pub full_duplex: bool,
+}
+
+impl Link {
+    /// Decodes the link state from the value of the STATUS register.
+    ///
+    /// If the link is down, the function returns `None`.
+    fn from_status(status: u32) -> Option<Self> {
+        if status & STATUS_LU == 0 {
+            return None;
+        }
+        let speed = match (status >> STATUS_SPEED_SHIFT) & STATUS_SPEED_MASK {
+            0b00 => 10,
+            0b01 => 100,
+            _ => 1000,
+        };
+        Some(Self {
+            speed,
+            full_duplex: status & STATUS_FD != 0,
+        })
+    }
+}
+
+impl fmt::Display for Link {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let duplex = if self.full_duplex { "full" } else { "half" };
+        write!(f, "{}Mb/s {duplex} duplex", self.speed)
+    }
+}
+
+/// Metadata of a received frame, reported by the NIC in the receive descriptors.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RxMeta {
+    /// The length of the frame, including the FCS.
+    pub len: usize,
+    /// The result of the verification of the IP checksum by the NIC: `true` if valid. `None` if
+    /// the checksum has not been verified.
+    pub ip_checksum: Option<bool>,
+    /// The result of the verification of the TCP or UDP checksum by the NIC: `true` if valid.
+    /// `None` if the checksum has not been verified.
+    pub l4_checksum: Option<bool>,
+    /// The raw checksum of the packet, computed by the NIC.
+    pub checksum: u16,
+    /// The 802.1Q tag stripped from the frame. Tags are stripped only once a VLAN is accepted.
+    pub vlan: Option<u16>,
+    /// The errors reported by the NIC, as a combination of `RX_ERR_*` flags.
+    pub errors: u8,
+}
+
+impl RxMeta {
+    /// Decodes the metadata of a frame of `len` bytes from the fields of its last descriptor.
+    fn decode(len: usize, status: u8, errors: u8, checksum: u16, special: u16) -> Self {
+        // When IXSM is set, the NIC has not checked anything
+        let checked = |calculated: u8, error: u8| {
+            (status & RX_STA_IXSM == 0 && status & calculated != 0).then_some(errors & error == 0)
+        };
+
+        Self {
+            len,
+            ip_checksum: checked(RX_STA_IPCS, RX_ERR_IPE),
+            l4_checksum: checked(RX_STA_TCPCS | RX_STA_UDPCS, RX_ERR_TCPE),
+            checksum,
+            vlan: (status & RX_STA_VP != 0).then_some(special),
+            errors,
+        }
+    }
+
+    /// Tells whether the frame has been received with errors.
+    ///
+    /// Such frames are only returned in store bad packets mode.
+    pub fn is_bad(&self) -> bool {
+        self.errors & RX_ERR_FRAME != 0
+    }
+}
+
+/// Counters of frames received with errors, by class of error.
+///
+/// A frame with several errors is counted in each of their classes.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RxErrorStats {
+    /// Frames with a CRC or alignment error.
+    pub crc: u64,
+    /// Frames with a symbol error.
+    pub symbol: u64,
+    /// Frames with a sequence error.
+    pub sequence: u64,
+    /// Frames with a carrier extension error.
+    pub carrier_ext: u64,
+    /// Frames with an RX data error.
+    pub data: u64,
+}
+
+impl RxErrorStats {
+    /// Counts a frame with the given errors, as `RX_ERR_*` flags.
+    fn count(&mut self, errors: u8) {
+        let classes = [
+            (RX_ERR_CE, &mut self.crc),
+            (RX_ERR_SE, &mut self.symbol),
+            (RX_ERR_SEQ, &mut self.sequence),
+            (RX_ERR_CXE, &mut self.carrier_ext),
+            (RX_ERR_RXE, &mut self.data),
+        ];
+        for (flag, counter) in classes {
+            if errors & flag != 0 {
+                *counter += 1;
+            }
+        }
+    }
+}
+
+/// State shared between a NIC and its interrupt handler.
+///
+/// The handler cannot lock the NIC since it may interrupt code holding the lock, so it only
+/// records what happened for the NIC to read later.
+#[derive(Default)]
+struct IntState {
+    /// The value of the STATUS register, updated on each link status change.
+    status: AtomicU32,
+    /// The number of receiver FIFO overruns.
+    rx_overruns: AtomicU32,
+    /// The number of receive sequence errors.
+    rx_seq_errors: AtomicU32,
+}
+
+impl IntState {
+    /// Handles an interrupt from the NIC whose registers are mapped by `bar0`.
+    ///
+    /// Since the interrupt line may be shared, the function does nothing if the interrupt was not
+    /// raised by the NIC.
+    fn handle(&self, bar0: &BAR) {
+        // Reading ICR acknowledges the interrupt
+        let icr = bar0.read::<u32>(REG_ICR as _) as u32;
+        // All ones means the device has been removed from the bus
+        if icr == 0 || icr == 0xffffffff {
+            return;
+        }
+
+        if icr & INT_RX != 0 {
+            self.handle_rx(icr);
+        }
+        if icr & IMS_LSC != 0 {
+            self.handle_link(bar0.read::<u32>(REG_STATUS as _) as _);
+        }
+        if icr & INT_ERR != 0 {
+            self.handle_error(icr);
+        }
+    }
+
+    /// Handles reception interrupts.
+    fn handle_rx(&self, icr: u32) {
+        if icr & IMS_RXO != 0 {
+            self.rx_overruns.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Handles link status changes, `status` being the new value of the STATUS register.
+    fn handle_link(&self, status: u32) {
+        self.status.store(status, Ordering::Release);
+    }
+
+    /// Handles error interrupts.
+    fn handle_error(&self, icr: u32) {
+        if icr & IMS_RXSEQ != 0 {
+            self.rx_seq_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
 /// Structure representing a Network Interface Card.
 pub struct NIC {
-    /// TODO doc
+    /// The description of the device.
+    info: &'static DeviceInfo,
+    /// The name of the interface.
+    name: String,
+    /// The index of the port on the controller. Always zero for single port devices.
+    port: u8,
+
+    /// The value of the PCI status register at probe time.
     status_reg: u16,
-    /// TODO doc
+    /// The value of the PCI command register.
     command_reg: u16,
 
     /// The BAR0 of the device.
     bar0: BAR,
-    /// The hook of the interrupt handler.
-    int_hook: CallbackHook,
+    /// The hook of the interrupt handler. `None` once the NIC has been shut down.
+    int_hook: Option<CallbackHook>,
+    /// State shared with the interrupt handler.
+    int_state: Arc<IntState>,
+    /// Tells whether the NIC has been shut down.
+    removed: bool,
+
+    /// Tells whether the interface is administratively up.
+    admin_up: bool,
 
-    /// Tells whether the EEPROM exist.
-    eeprom_exists: bool,
+    /// The NIC's EEPROM. `None` if absent.
+    eeprom: Option<Eeprom>,
 
     /// The NIC's mac address.
     mac: [u8; 6],
-
-    /// The list of receive descriptors.
-    rx_descs: *mut RXDesc,
+    /// The Maximum Transmission Unit.
+    mtu: usize,
+    /// The multicast groups joined by the interface, along with their reference count.
+    multicast: Vec<(MAC, u32)>,
+    /// The maximum number of multicast groups filtered through the Multicast Table Array.
+    multicast_limit: usize,
+    /// The secondary unicast addresses of the interface, along with their reference count.
+    unicast: Vec<(MAC, u32)>,
+    /// The number of users of unicast promiscuous mode.
+    promisc: u32,
+    /// The number of users of multicast promiscuous mode.
+    allmulti: u32,
+    /// Tells whether broadcast packets are accepted.
+    broadcast: bool,
+    /// The content of the VLAN Filter Table Array: one bit per accepted VLAN ID.
+    vfta: [u32; VFTA_COUNT as usize],
+    /// Tells whether frames with reception errors are passed up instead of being dropped.
+    store_bad: bool,
+    /// Counters of frames received with errors.
+    rx_errors: RxErrorStats,
+
+    /// The list of receive descriptors. `None` if not allocated.
+    rx_ring: Option<DmaBuffer>,
+    /// The number of descriptors in the receive ring.
+    rx_size: usize,
+    /// The receive buffers. `None` if not allocated.
+    rx_buffs: Option<RingBuffers>,
+    /// The number of descriptors in use in the receive ring. It may be lower than the size of the
+    /// ring if memory was short when allocating buffers.
+    rx_count: usize,
     /// The cursor in the receive ring buffer.
     rx_cur: usize,
 
-    /// The list of transmit descriptors.
-    tx_descs: *mut TXDesc,
+    /// The list of transmit descriptors. `None` if not allocated.
+    tx_ring: Option<DmaBuffer>,
+    /// The number of descriptors in the transmit ring.
+    tx_size: usize,
+    /// The transmit buffers. `None` if not allocated.
+    tx_buffs: Option<RingBuffers>,
+    /// The number of descriptors in use in the transmit ring.
+    tx_count: usize,
     /// The cursor in the transmit ring buffer.
     tx_cur: usize,
 }
 
 impl NIC {
     /// Creates a new instance using the given device.
-    pub fn new(dev: &dyn PhysicalDevice) -> Result<Self, &str> {
-        let status_reg = dev
-            .get_status_reg()
-            .ok_or("Invalid PCI informations for NIC")?;
-        let command_reg = dev
-            .get_command_reg()
-            .ok_or("Invalid PCI informations for NIC")?;
-
-        let bar0 = dev.get_bars()[0].clone().ok_or("Invalid BAR for NIC")?;
-
-        let int_line = dev.get_interrupt_line().ok_or("Invalid BAR for NIC")?;
-        let int_hook = event::register_callback(int_line as _, |_, _, _, _| {
-            // TODO
-            todo!();
-        })
-        .map_err(|_| "Memory allocation failed")?;
-
-        let rx_order = buddy::get_order(math::ceil_div(
-            RX_DESC_COUNT * size_of::<RXDesc>(),
-            memory::PAGE_SIZE,
-        ));
-        let rx_descs = buddy::alloc_kernel(rx_order).map_err(|_| "Memory allocation failed")?;
-
-        let tx_order = buddy::get_order(math::ceil_div(
-            TX_DESC_COUNT * size_of::<TXDesc>(),
-            memory::PAGE_SIZE,
-        ));
-        let Ok(tx_descs) = buddy::alloc_kernel(tx_order) else {
-            buddy::free_kernel(rx_descs, rx_order);
-            return Err("Memory allocation failed");
+    ///
+    /// Arguments:
+    /// - `info` is the entry of the device table matching `dev`
+    /// - `name` is the name of the interface
+    pub fn new(
+        dev: &dyn PhysicalDevice,
+        info: &'static DeviceInfo,
+        name: String,
+    ) -> Result<Self, E1000Error> {
+        let location = PciLocation::of(dev).ok_or(E1000Error::MissingPciInfo)?;
+        let status_reg = dev.get_status_reg().ok_or(E1000Error::MissingPciInfo)?;
+
+        let bar0 = dev.get_bars()[0].clone().ok_or(E1000Error::MissingBar)?;
+
+        let int_line = dev.get_interrupt_line().ok_or(E1000Error::MissingIrq)?;
+
+        // Enable bus mastering, required for the NIC to access descriptors with DMA
+        let command_reg = enable_command(location, PCI_COMMAND_MEMORY | PCI_COMMAND_MASTER);
+
+        let int_state = Arc::new(IntState::default()).map_err(|_| E1000Error::Alloc)?;
+        let int_hook = {
+            let bar0 = bar0.clone();
+            let int_state = int_state.clone();
+            event::register_callback(int_line as _, move |_, _, _, _| {
+                int_state.handle(&bar0);
+                // The kernel cannot tell which hook claimed an interrupt, so the other hooks on
+                // the line always get to handle it
+                CallbackResult::Continue
+            })
+            .map_err(|_| E1000Error::Alloc)?
         };
 
+        let rx_size = ring_size(DEFAULT_RX_DESCS);
+        let tx_size = ring_size(DEFAULT_TX_DESCS);
+        let rx_ring = alloc_descs::<RXDesc>(rx_size)?;
+        let tx_ring = alloc_descs::<TXDesc>(tx_size)?;
+
         let mut n = Self {
+            info,
+            name,
+            port: 0,
+
             status_reg,
             command_reg,
 
             bar0,
-            int_hook,
+            int_hook: Some(int_hook),
+            int_state,
+            removed: false,
 
-            eeprom_exists: false,
+            admin_up: true,
 
-            mac: [0; 6],
+            eeprom: None,
 
-            rx_descs: rx_descs as _,
+            mac: [0; 6],
+            mtu: DEFAULT_MTU,
+            multicast: Vec::new(),
+            unicast: Vec::new(),
+            multicast_limit: DEFAULT_MULTICAST_LIMIT,
+            promisc: 0,
+            allmulti: 0,
+            broadcast: true,
+            vfta: [0; VFTA_COUNT as usize],
+            store_bad: false,
+            rx_errors: RxErrorStats::default(),
+
+            rx_ring: Some(rx_ring),
+            rx_size,
+            rx_buffs: None,
+            rx_count: 0,
             rx_cur: 0,
 
-            tx_descs: tx_descs as _,
+            tx_ring: Some(tx_ring),
+            tx_size,
+            tx_buffs: None,
+            tx_count: 0,
             tx_cur: 0,
         };
+        n.detect_port();
+        // The reset clears the address preloaded by the firmware in the Receive Address
+        // registers, so it is saved as a fallback for devices without EEPROM
+        let preloaded_mac = n.read_ra_mac();
+        n.reset()?;
         n.detect_eeprom();
-        n.read_mac();
-        n.init_desc().map_err(|_| "Memory allocation failed")?;
+        n.read_mac(preloaded_mac);
+        n.setup_link();
+        n.init_vlan();
+        n.int_state
+            .status
+            .store(n.read_command(REG_STATUS), Ordering::Release);
+        n.write_ra(0, Some(&n.mac));
+        n.update_unicast();
+        n.clear_mta();
+        n.clear_stats();
+        n.init_desc().map_err(|_| E1000Error::Alloc)?;
 
         Ok(n)
     }
@@ -297,114 +874,916 @@ impl NIC {
         self.bar0.write::<u32>(addr as _, val as _);
     }
 
-    /// Detects whether the EEPROM exists.
-    fn detect_eeprom(&mut self) {
-        self.eeprom_exists = self.read_command(REG_EECD) & (1 << 8) != 0;
+    /// Performs a global reset of the controller, followed by a reset of the PHY.
+    ///
+    /// Interrupts are disabled before and after the reset.
+    fn reset(&self) -> Result<(), E1000Error> {
+        self.write_command(REG_IMC, !0);
+
+        self.write_command(REG_CTRL, self.read_command(REG_CTRL) | CTRL_RST);
+        (0..RESET_ATTEMPTS)
+            .find(|_| self.read_command(REG_CTRL) & CTRL_RST == 0)
+            .ok_or(E1000Error::ResetTimeout)?;
+
+        // The reset re-enables interrupts. Disable them and clear pending ones
+        self.write_command(REG_IMC, !0);
+        self.read_command(REG_ICR);
+
+        self.reset_phy();
+        Ok(())
     }
 
-    /// Reads from the EEPROM at address `addr`.
-    fn eeprom_read(&self, addr: u8) -> u32 {
-        // Acquire EEPROM
-        self.write_command(REG_EECD, self.read_command(REG_EECD) | (1 << 6));
-
-        // Specify read address
-        self.write_command(REG_EERD, 1 | ((addr as u32) << 8));
-
-        let data = if self.eeprom_exists {
-            loop {
-                let val = self.read_command(REG_EERD);
-                if val & (1 << 4) != 0 {
-                    break (val >> 16) & 0xffff;
-                }
-            }
+    /// Resets the PHY, if the device has one and the manageability engine does not prevent it.
+    fn reset_phy(&self) {
+        if self.info.phy() == PhyType::Tbi {
+            return;
+        }
+        if self.info.family.has_quirk(QUIRK_MANAGEABILITY)
+            && self.read_command(REG_MANC) & MANC_BLK_PHY_RST != 0
+        {
+            return;
+        }
+
+        let ctrl = self.read_command(REG_CTRL);
+        self.write_command(REG_CTRL, ctrl | CTRL_PHY_RST);
+        for _ in 0..PHY_RESET_DELAY {
+            self.read_command(REG_STATUS);
+        }
+        self.write_command(REG_CTRL, ctrl & !CTRL_PHY_RST);
+    }
+
+    /// Sets the link up, letting the controller detect speed and duplex by itself.
+    fn setup_link(&self) {
+        let mut ctrl = self.read_command(REG_CTRL);
+        ctrl &= !(CTRL_LRST | CTRL_ILOS | CTRL_FRCSPD | CTRL_FRCDPLX | CTRL_PHY_RST);
+        ctrl |= CTRL_SLU;
+        if self.info.phy() != PhyType::Tbi {
+            ctrl |= CTRL_ASDE;
+        }
+        self.write_command(REG_CTRL, ctrl);
+    }
+
+    /// Clears the VLAN Filter Table Array.
+    ///
+    /// Tag stripping and insertion stay disabled until a VLAN is accepted, since
+    /// [`net::Interface::read`] cannot report stripped tags.
+    fn init_vlan(&self) {
+        self.write_command(REG_VET, ETHERTYPE_VLAN);
+        for i in 0..VFTA_COUNT {
+            self.write_command(REG_VFTA + i * 4, 0);
+        }
+        self.write_command(REG_CTRL, self.read_command(REG_CTRL) & !CTRL_VME);
+    }
+
+    /// Tells whether at least one VLAN is accepted.
+    fn has_vlans(&self) -> bool {
+        self.vfta.iter().any(|e| *e != 0)
+    }
+
+    /// Accepts or rejects tagged packets from the VLAN with ID `vid`.
+    ///
+    /// Untagged packets are always accepted. Once at least one VLAN is accepted, tagged packets
+    /// from other VLANs are dropped.
+    fn set_vlan(&mut self, vid: u16, accept: bool) -> Result<(), E1000Error> {
+        if self.removed {
+            return Err(E1000Error::Removed);
+        }
+        // VLAN 0 marks priority tagged packets and 4095 is reserved
+        if vid == 0 || vid >= VLAN_VID_MASK {
+            return Err(E1000Error::InvalidArgument);
+        }
+
+        let (i, bit) = ((vid >> 5) as usize, 1 << (vid & 0x1f));
+        if accept {
+            self.vfta[i] |= bit;
+        } else {
+            self.vfta[i] &= !bit;
+        }
+        self.write_command(REG_VFTA + i as u16 * 4, self.vfta[i]);
+        self.update_rx_filter();
+
+        // Tags are stripped only while VLANs are in use
+        let ctrl = self.read_command(REG_CTRL);
+        if self.has_vlans() {
+            self.write_command(REG_CTRL, ctrl | CTRL_VME);
+        } else {
+            self.write_command(REG_CTRL, ctrl & !CTRL_VME);
+        }
+        Ok(())
+    }
+
+    /// Accepts tagged packets from the VLAN with ID `vid`.
+    pub fn add_vlan(&mut self, vid: u16) -> Result<(), E1000Error> {
+        self.set_vlan(vid, true)
+    }
+
+    /// Stops accepting tagged packets from the VLAN with ID `vid`.
+    pub fn remove_vlan(&mut self, vid: u16) -> Result<(), E1000Error> {
+        self.set_vlan(vid, false)
+    }
+
+    /// Clears the Multicast Table Array.
+    fn clear_mta(&self) {
+        for i in 0..MTA_COUNT {
+            self.write_command(REG_MTA + i * 4, 0);
+        }
+    }
+
+    /// Joins the multicast group with the given address.
+    ///
+    /// Groups are reference counted: a group joined several times must be left as many times.
+    ///
+    /// If the address is not a multicast address, the function returns an error.
+    pub fn join_multicast(&mut self, mac: MAC) -> Result<(), E1000Error> {
+        if self.removed {
+            return Err(E1000Error::Removed);
+        }
+        if mac[0] & 1 == 0 {
+            return Err(E1000Error::InvalidArgument);
+        }
+
+        if let Some((_, count)) = self.multicast.iter_mut().find(|(m, _)| *m == mac) {
+            *count += 1;
+            return Ok(());
+        }
+        self.multicast
+            .push((mac, 1))
+            .map_err(|_| E1000Error::Alloc)?;
+        self.update_multicast();
+        Ok(())
+    }
+
+    /// Leaves the multicast group with the given address.
+    ///
+    /// If the group has not been joined, the function returns an error.
+    pub fn leave_multicast(&mut self, mac: MAC) -> Result<(), E1000Error> {
+        if self.removed {
+            return Err(E1000Error::Removed);
+        }
+
+        let i = self
+            .multicast
+            .iter()
+            .position(|(m, _)| *m == mac)
+            .ok_or(E1000Error::InvalidArgument)?;
+        self.multicast[i].1 -= 1;
+        if self.multicast[i].1 == 0 {
+            self.multicast.remove(i);
+            self.update_multicast();
+        }
+        Ok(())
+    }
+
+    /// Sets the maximum number of multicast groups filtered through the Multicast Table Array.
+    ///
+    /// When more groups are joined, the NIC accepts all multicast packets instead.
+    pub fn set_multicast_limit(&mut self, limit: usize) {
+        self.multicast_limit = limit;
+        if !self.removed {
+            self.update_multicast();
+        }
+    }
+
+    /// Enables or disables unicast promiscuous mode, in which all packets are accepted regardless
+    /// of their destination.
+    ///
+    /// The mode is reference counted: it stays enabled until it has been disabled as many times
+    /// as it has been enabled. Disabling it more times than it has been enabled is an error.
+    pub fn set_promiscuous(&mut self, enable: bool) -> Result<(), E1000Error> {
+        if self.removed {
+            return Err(E1000Error::Removed);
+        }
+        self.promisc = if enable {
+            self.promisc + 1
         } else {
-            // TODO
-            todo!();
+            self.promisc
+                .checked_sub(1)
+                .ok_or(E1000Error::InvalidArgument)?
         };
+        self.update_rx_filter();
+        Ok(())
+    }
 
-        // Release EEPROM
-        self.write_command(REG_EECD, self.read_command(REG_EECD) & !(1 << 6));
+    /// Enables or disables multicast promiscuous mode, in which all multicast packets are
+    /// accepted.
+    ///
+    /// The mode is reference counted, like [`Self::set_promiscuous`].
+    pub fn set_allmulti(&mut self, enable: bool) -> Result<(), E1000Error> {
+        if self.removed {
+            return Err(E1000Error::Removed);
+        }
+        self.allmulti = if enable {
+            self.allmulti + 1
+        } else {
+            self.allmulti
+                .checked_sub(1)
+                .ok_or(E1000Error::InvalidArgument)?
+        };
+        self.update_rx_filter();
+        Ok(())
+    }
 
-        data
+    /// Sets whether broadcast packets are accepted.
+    pub fn set_broadcast(&mut self, accept: bool) {
+        self.broadcast = accept;
+        if !self.removed {
+            self.update_rx_filter();
+        }
     }
 
-    /// Reads the MAC address from the NIC's EEPROM.
-    fn read_mac(&mut self) {
-        let val = self.eeprom_read(0);
-        self.mac[0] = (val & 0xff) as u8;
-        self.mac[1] = ((val >> 8) & 0xff) as u8;
+    /// Returns the receive filtering flags of RCTL matching the current state.
+    fn rx_filter_flags(&self) -> u32 {
+        let mut flags = MULTICAST_OFFSET << RCTL_MO_SHIFT;
+        if self.promisc > 0 {
+            flags |= RCTL_UPE | RCTL_MPE;
+        }
+        if self.unicast.len() >= RA_COUNT as usize {
+            flags |= RCTL_UPE;
+        }
+        if self.allmulti > 0 || self.multicast.len() > self.multicast_limit {
+            flags |= RCTL_MPE;
+        }
+        if self.broadcast {
+            flags |= RCTL_BAM;
+        }
+        if self.has_vlans() {
+            flags |= RCTL_VFE;
+        }
+        flags
+    }
 
-        let val = self.eeprom_read(1);
-        self.mac[2] = (val & 0xff) as u8;
-        self.mac[3] = ((val >> 8) & 0xff) as u8;
+    /// Updates the receive filtering flags of RCTL. The receiver keeps running.
+    fn update_rx_filter(&self) {
+        let mask = RCTL_UPE | RCTL_MPE | RCTL_BAM | RCTL_VFE | (0b11 << RCTL_MO_SHIFT);
+        let rctl = self.read_command(REG_RCTL) & !mask;
+        self.write_command(REG_RCTL, rctl | self.rx_filter_flags());
+    }
 
-        let val = self.eeprom_read(2);
-        self.mac[4] = (val & 0xff) as u8;
-        self.mac[5] = ((val >> 8) & 0xff) as u8;
+    /// Programs the Multicast Table Array with the joined groups, or enables multicast
+    /// promiscuous mode if there are too many of them.
+    fn update_multicast(&self) {
+        let mut mta = [0u32; MTA_COUNT as usize];
+        for (mac, _) in self.multicast.iter() {
+            let hash = mta_hash(mac);
+            mta[(hash >> 5) as usize] |= 1 << (hash & 0x1f);
+        }
+        for (i, val) in mta.iter().enumerate() {
+            self.write_command(REG_MTA + i as u16 * 4, *val);
+        }
+        self.update_rx_filter();
     }
 
-    /// Initializes transmit and receive descriptors.
-    fn init_desc(&self) -> Result<(), Errno> {
-        // Set interrupts mask
-        self.write_command(REG_IMS, IMS_TXQE | IMS_RXDMT0);
+    /// Clears the statistics registers, which are reset on read.
+    fn clear_stats(&self) {
+        for reg in (REG_STATS_BEGIN..REG_STATS_END).step_by(4) {
+            self.read_command(reg);
+        }
+    }
 
-        // Init receive ring buffer
-        let rx_buffs_order = buddy::get_order(math::ceil_div(
-            RX_DESC_COUNT * RX_BUFF_SIZE,
-            memory::PAGE_SIZE,
-        ));
-        let rx_buffs = buddy::alloc_kernel(rx_buffs_order)?;
-        for i in 0..RX_DESC_COUNT {
-            let desc = unsafe { &mut *self.rx_descs.add(i) };
-            let ptr = unsafe { rx_buffs.add(i * TX_BUFF_SIZE) };
+    /// Returns the state of the link, or `None` if the link is down.
+    pub fn link(&self) -> Option<Link> {
+        Link::from_status(self.int_state.status.load(Ordering::Acquire))
+    }
 
-            *desc = RXDesc::default();
-            desc.addr = ptr as _;
+    /// Sets the administrative state of the interface.
+    ///
+    /// When down, the receiver and transmitter are disabled.
+    pub fn set_admin_up(&mut self, up: bool) {
+        if self.removed || self.admin_up == up {
+            return;
         }
+        self.admin_up = up;
 
-        // Set receive ring buffer address
-        let phys_ptr = memory::kern_to_phys(self.rx_descs);
-        self.write_command(REG_RDBAL, ((phys_ptr as u64) & 0xffffffff) as _);
-        self.write_command(REG_RDBAH, ((phys_ptr as u64) >> 32) as _);
+        let (rctl, tctl) = (self.read_command(REG_RCTL), self.read_command(REG_TCTL));
+        if up {
+            self.write_command(REG_RCTL, rctl | RCTL_EN);
+            self.write_command(REG_TCTL, tctl | TCTL_EN);
+        } else {
+            self.write_command(REG_RCTL, rctl & !RCTL_EN);
+            self.write_command(REG_TCTL, tctl & !TCTL_EN);
+        }
+    }
 
-        // Set receive ring buffer length
-        self.write_command(REG_RDLEN, (RX_DESC_COUNT * size_of::<RXDesc>()) as u32);
+    /// Returns the number of receiver FIFO overruns and receive sequence errors reported by
+    /// interrupts, in this order.
+    pub fn int_errors(&self) -> (u32, u32) {
+        (
+            self.int_state.rx_overruns.load(Ordering::Relaxed),
+            self.int_state.rx_seq_errors.load(Ordering::Relaxed),
+        )
+    }
 
-        // Set receive ring buffer head and tail
-        self.write_command(REG_RDH, 0);
-        self.write_command(REG_RDT, (RX_DESC_COUNT - 1) as _);
+    /// Tells whether the device is still present on the bus.
+    ///
+    /// When a device is removed, reads from its registers return all ones.
+    fn is_present(&self) -> bool {
+        self.read_command(REG_STATUS) != 0xffffffff
+    }
 
-        // Set receive flags
-        let mut flags = RCTL_EN | RCTL_UPE | RCTL_MPE | RCTL_BAM;
-        flags |= RCTL_BSEX | (0b01 << 16); // 16K buffer
-        self.write_command(REG_RCTL, flags);
+    /// Stops the NIC and releases its rings and interrupt handler.
+    ///
+    /// After this function returns, the NIC cannot be used anymore. This works even if the device
+    /// has already been removed from the bus.
+    pub fn shutdown(&mut self) {
+        if self.removed {
+            return;
+        }
+        self.removed = true;
+
+        if self.is_present() {
+            // Mask all interrupts and stop the receiver and transmitter
+            self.write_command(REG_IMC, !0);
+            self.write_command(REG_RCTL, self.read_command(REG_RCTL) & !RCTL_EN);
+            self.write_command(REG_TCTL, self.read_command(REG_TCTL) & !TCTL_EN);
+            // Flush posted writes
+            self.read_command(REG_STATUS);
+
+            // Disabling the queues does not stop DMA in flight. The reset does, and clears the
+            // addresses of the rings before their memory is given back
+            if let Err(e) = self.reset() {
+                kernel::println!("e1000: {}: cannot reset, leaking rings: {e}", self.name);
+                self.int_hook = None;
+                mem::forget(self.rx_buffs.take());
+                mem::forget(self.rx_ring.take());
+                mem::forget(self.tx_buffs.take());
+                mem::forget(self.tx_ring.take());
+                return;
+            }
+        }
 
-        // Init transmit ring buffer
-        let tx_buffs_order = buddy::get_order(math::ceil_div(
-            TX_DESC_COUNT * TX_BUFF_SIZE,
-            memory::PAGE_SIZE,
-        ));
-        let tx_buffs = buddy::alloc_kernel(tx_buffs_order)?;
-        for i in 0..TX_DESC_COUNT {
-            let desc = unsafe { &mut *self.tx_descs.add(i) };
-            let ptr = unsafe { tx_buffs.add(i * TX_BUFF_SIZE) };
+        // Unregister the interrupt handler
+        self.int_hook = None;
+        self.free_rings();
+    }
+
+    /// Frees the descriptor rings and their buffers.
+    fn free_rings(&mut self) {
+        self.rx_buffs = None;
+        self.rx_ring = None;
+
+        self.tx_buffs = None;
+        self.tx_ring = None;
+    }
+
+    /// Returns the receive descriptors. Null if not allocated.
+    fn rx_descs(&self) -> *mut RXDesc {
+        self.rx_ring
+            .as_ref()
+            .map_or(ptr::null_mut(), DmaBuffer::as_ptr)
+    }
+
+    /// Returns the transmit descriptors. Null if not allocated.
+    fn tx_descs(&self) -> *mut TXDesc {
+        self.tx_ring
+            .as_ref()
+            .map_or(ptr::null_mut(), DmaBuffer::as_ptr)
+    }
+
+    /// Returns the address for the CPU of the receive buffer with index `i`. Null if not
+    /// allocated.
+    fn rx_buff(&self, i: usize) -> *mut u8 {
+        self.rx_buffs
+            .as_ref()
+            .map_or(ptr::null_mut(), |buffs| buffs.get(i))
+    }
+
+    /// Returns the address for the CPU of the transmit buffer with index `i`. Null if not
+    /// allocated.
+    fn tx_buff(&self, i: usize) -> *mut u8 {
+        self.tx_buffs
+            .as_ref()
+            .map_or(ptr::null_mut(), |buffs| buffs.get(i))
+    }
+
+    /// Returns the number of descriptors of a ring that can be used with `allocated` buffers,
+    /// `wanted` being the size of the ring.
+    ///
+    /// `kind` is the kind of ring, used in the warning printed if the ring is partially filled.
+    fn ring_count(&self, kind: &str, allocated: usize, wanted: usize) -> Result<usize, E1000Error> {
+        let count = allocated - allocated % RING_GRANULARITY;
+        if count == 0 {
+            return Err(E1000Error::Alloc);
+        }
+        if count < wanted {
+            kernel::println!(
+                "e1000: {}: low memory, using {count} of {wanted} {kind} descriptors",
+                self.name
+            );
+        }
+        Ok(count)
+    }
+
+    /// Allocates receive buffers of `size` bytes and assigns them to the receive descriptors.
+    ///
+    /// The previous buffers are freed only once the new ones are allocated, so they are kept if
+    /// the allocation fails. The receiver must be stopped.
+    fn alloc_rx_buffs(&mut self, size: usize) -> Result<(), E1000Error> {
+        let buffs = RingBuffers::alloc(size, self.rx_size)?;
+        let count = self.ring_count("receive", buffs.len(), self.rx_size)?;
+        for i in 0..count {
+            let desc = RXDesc {
+                addr: buffs.bus_addr(i),
+                ..Default::default()
+            };
+            unsafe {
+                ptr::write_volatile(self.rx_descs().add(i), desc);
+            }
+        }
 
-            *desc = TXDesc::default();
-            desc.addr = ptr as _;
-            desc.status = TX_STA_DD;
+        self.rx_buffs = Some(buffs);
+        self.rx_count = count;
+        self.rx_cur = 0;
+        Ok(())
+    }
+
+    /// Allocates transmit buffers and assigns them to the transmit descriptors.
+    fn alloc_tx_buffs(&mut self) -> Result<(), E1000Error> {
+        let buffs = RingBuffers::alloc(TX_BUFF_SIZE, self.tx_size)?;
+        let count = self.ring_count("transmit", buffs.len(), self.tx_size)?;
+        for i in 0..count {
+            let desc = TXDesc {
+                addr: buffs.bus_addr(i),
+                status: TX_STA_DD,
+                ..Default::default()
+            };
+            unsafe {
+                ptr::write_volatile(self.tx_descs().add(i), desc);
+            }
         }
 
-        // Set transmit ring buffer address
-        let phys_ptr = memory::kern_to_phys(self.tx_descs);
-        self.write_command(REG_TDBAL, ((phys_ptr as u64) & 0xffffffff) as _);
-        self.write_command(REG_TDBAH, ((phys_ptr as u64) >> 32) as _);
+        self.tx_buffs = Some(buffs);
+        self.tx_count = count;
+        self.tx_cur = 0;
+        Ok(())
+    }
+
+    /// Programs the address, length, head and tail of the receive ring.
+    fn setup_rx_ring(&self) {
+        let bus_addr = self.rx_ring.as_ref().map_or(0, DmaBuffer::bus_addr);
+        self.write_command(REG_RDBAL, (bus_addr & 0xffffffff) as _);
+        self.write_command(REG_RDBAH, (bus_addr >> 32) as _);
+        self.write_command(REG_RDLEN, (self.rx_count * size_of::<RXDesc>()) as u32);
+        self.write_command(REG_RDH, 0);
+        self.write_command(REG_RDT, (self.rx_count - 1) as _);
+    }
+
+    /// Programs the address, length, head and tail of the transmit ring.
+    fn setup_tx_ring(&self) {
+        let bus_addr = self.tx_ring.as_ref().map_or(0, DmaBuffer::bus_addr);
+        self.write_command(REG_TDBAL, (bus_addr & 0xffffffff) as _);
+        self.write_command(REG_TDBAH, (bus_addr >> 32) as _);
+        self.write_command(REG_TDLEN, (self.tx_count * size_of::<TXDesc>()) as u32);
+        self.write_command(REG_TDH, 0);
+        self.write_command(REG_TDT, 0);
+    }
 
-        // Set transmit ring buffer length
-        self.write_command(REG_TDLEN, (TX_DESC_COUNT * size_of::<TXDesc>()) as u32);
+    /// Clears the receive descriptors and rewinds the receive ring, dropping the frames it holds.
+    ///
+    /// The receiver must be stopped.
+    fn reset_rx_ring(&mut self) {
+        let descs = self.rx_descs();
+        for i in 0..self.rx_count {
+            let desc = unsafe { descs.add(i) };
+            let new = RXDesc {
+                addr: desc_read!(desc, addr),
+                ..Default::default()
+            };
+            unsafe {
+                ptr::write_volatile(desc, new);
+            }
+        }
+        self.rx_cur = 0;
+        wmb();
+        self.write_command(REG_RDH, 0);
+        self.write_command(REG_RDT, (self.rx_count - 1) as _);
+    }
 
-        // Set transmit ring buffer head and tail
+    /// Marks the transmit descriptors as done and rewinds the transmit ring.
+    ///
+    /// The transmitter must be stopped.
+    fn reset_tx_ring(&mut self) {
+        let descs = self.tx_descs();
+        for i in 0..self.tx_count {
+            let desc = unsafe { descs.add(i) };
+            let new = TXDesc {
+                addr: desc_read!(desc, addr),
+                status: TX_STA_DD,
+                ..Default::default()
+            };
+            unsafe {
+                ptr::write_volatile(desc, new);
+            }
+        }
+        self.tx_cur = 0;
+        wmb();
         self.write_command(REG_TDH, 0);
         self.write_command(REG_TDT, 0);
+    }
+
+    /// Returns the number of descriptors in the receive and transmit rings, in this order.
+    pub fn ring_sizes(&self) -> (usize, usize) {
+        (self.rx_size, self.tx_size)
+    }
+
+    /// Resizes the receive and transmit rings to `rx` and `tx` descriptors respectively.
+    ///
+    /// Packets being transmitted are sent before the rings are rebuilt. Packets pending in the
+    /// receive ring are dropped.
+    ///
+    /// Sizes must be multiples of 8, and at most 4096. Otherwise, the function returns an error.
+    pub fn resize_rings(&mut self, rx: usize, tx: usize) -> Result<(), E1000Error> {
+        if self.removed {
+            return Err(E1000Error::Removed);
+        }
+        if ring_size(rx) != rx || ring_size(tx) != tx {
+            return Err(E1000Error::InvalidArgument);
+        }
+
+        // Stop the transmitter once pending packets have been sent, then the receiver
+        let (rctl, tctl) = (self.read_command(REG_RCTL), self.read_command(REG_TCTL));
+        if tctl & TCTL_EN != 0 {
+            (0..TX_DRAIN_ATTEMPTS)
+                .find(|_| self.read_command(REG_TDH) == self.read_command(REG_TDT))
+                .ok_or(E1000Error::TxTimeout)?;
+        }
+        self.write_command(REG_TCTL, tctl & !TCTL_EN);
+        self.write_command(REG_RCTL, rctl & !RCTL_EN);
+        self.read_command(REG_STATUS);
+
+        // A ring that cannot be resized keeps its descriptors and buffers, but is rewound like a
+        // new one
+        let rx_res = self.resize_rx_ring(rx);
+        if rx_res.is_ok() {
+            self.setup_rx_ring();
+        } else {
+            self.reset_rx_ring();
+        }
+        let tx_res = self.resize_tx_ring(tx);
+        if tx_res.is_ok() {
+            self.setup_tx_ring();
+        } else {
+            self.reset_tx_ring();
+        }
+        self.write_command(REG_RCTL, rctl);
+        self.write_command(REG_TCTL, tctl);
+        rx_res.and(tx_res)
+    }
+
+    /// Replaces the receive ring with a ring of `size` descriptors.
+    ///
+    /// On failure, the previous ring is kept.
+    fn resize_rx_ring(&mut self, size: usize) -> Result<(), E1000Error> {
+        let ring = alloc_descs::<RXDesc>(size)?;
+        let old_ring = self.rx_ring.replace(ring);
+        let old_size = mem::replace(&mut self.rx_size, size);
+
+        let res = self.alloc_rx_buffs(rx_buff_size(self.mtu));
+        if res.is_err() {
+            self.rx_ring = old_ring;
+            self.rx_size = old_size;
+        }
+        res
+    }
+
+    /// Replaces the transmit ring with a ring of `size` descriptors.
+    ///
+    /// On failure, the previous ring is kept.
+    fn resize_tx_ring(&mut self, size: usize) -> Result<(), E1000Error> {
+        let ring = alloc_descs::<TXDesc>(size)?;
+        let old_ring = self.tx_ring.replace(ring);
+        let old_size = mem::replace(&mut self.tx_size, size);
+
+        let res = self.alloc_tx_buffs();
+        if res.is_err() {
+            self.tx_ring = old_ring;
+            self.tx_size = old_size;
+        }
+        res
+    }
+
+    /// Returns the counters of frames received with errors.
+    pub fn rx_error_stats(&self) -> RxErrorStats {
+        self.rx_errors
+    }
+
+    /// Enables or disables the store bad packets mode.
+    ///
+    /// In this diagnostic mode, the NIC keeps frames received with errors instead of discarding
+    /// them, and [`Self::receive`] returns them with their errors.
+    pub fn set_store_bad_packets(&mut self, enable: bool) -> Result<(), E1000Error> {
+        if self.removed {
+            return Err(E1000Error::Removed);
+        }
+        self.store_bad = enable;
+        let rctl = self.read_command(REG_RCTL);
+        if enable {
+            self.write_command(REG_RCTL, rctl | RCTL_SBP);
+        } else {
+            self.write_command(REG_RCTL, rctl & !RCTL_SBP);
+        }
+        Ok(())
+    }
+
+    /// Returns the RCTL flags selecting the size of the receive buffers and long packets
+    /// reception.
+    fn rx_size_flags(&self) -> u32 {
+        let mut flags = rctl_buff_size(rx_buff_size(self.mtu));
+        if max_frame_len(self.mtu) > MAX_STD_FRAME_LEN {
+            flags |= RCTL_LPE;
+        }
+        flags
+    }
+
+    /// Returns the MTU of the interface.
+    pub fn mtu(&self) -> usize {
+        self.mtu
+    }
+
+    /// Sets the MTU of the interface.
+    ///
+    /// The receive buffers are reallocated to fit the largest frame. Packets pending in the
+    /// receive ring are dropped.
+    ///
+    /// If the MTU is not between 68 and 16110 bytes, the function returns an error.
+    pub fn set_mtu(&mut self, mtu: usize) -> Result<(), E1000Error> {
+        if self.removed {
+            return Err(E1000Error::Removed);
+        }
+        if !(MIN_MTU..=MAX_MTU).contains(&mtu) {
+            return Err(E1000Error::InvalidArgument);
+        }
+
+        let size = rx_buff_size(mtu);
+        let rctl = self.read_command(REG_RCTL);
+        let res = if self.rx_buffs.as_ref().map(RingBuffers::buff_size) != Some(size) {
+            // Stop the receiver while its buffers are replaced
+            self.write_command(REG_RCTL, rctl & !RCTL_EN);
+            self.read_command(REG_STATUS);
+
+            let res = self.alloc_rx_buffs(size);
+            if res.is_ok() {
+                self.setup_rx_ring();
+            }
+            res
+        } else {
+            Ok(())
+        };
+        if res.is_ok() {
+            self.mtu = mtu;
+        }
+
+        let mask = RCTL_BSEX | (0b11 << RCTL_BSIZE_SHIFT) | RCTL_LPE;
+        self.write_command(REG_RCTL, (rctl & !mask) | self.rx_size_flags());
+        res
+    }
+
+    /// Detects the index of the port of the NIC on multi-port controllers.
+    fn detect_port(&mut self) {
+        if self.info.ports > 1 {
+            let status = self.read_command(REG_STATUS);
+            self.port = ((status >> STATUS_LAN_ID_SHIFT) & STATUS_LAN_ID_MASK) as _;
+        }
+    }
+
+    /// Detects whether the EEPROM exists.
+    ///
+    /// If the EEPROM exists but its checksum is invalid, a warning is printed.
+    fn detect_eeprom(&mut self) {
+        self.eeprom = Eeprom::detect(&self.bar0, self.info);
+
+        let Some(eeprom) = &self.eeprom else {
+            return;
+        };
+        match eeprom.is_checksum_valid() {
+            Ok(true) => {}
+            Ok(false) => kernel::println!(
+                "e1000: {}: invalid EEPROM checksum, the MAC address and settings may be wrong",
+                self.name
+            ),
+            Err(e) => kernel::println!("e1000: {}: cannot check EEPROM checksum: {e}", self.name),
+        }
+    }
+
+    /// Returns the full image of the NIC's EEPROM.
+    pub fn eeprom_dump(&self) -> Result<Vec<u16>, E1000Error> {
+        self.eeprom
+            .as_ref()
+            .ok_or(E1000Error::MissingEeprom)?
+            .dump()
+    }
+
+    /// Reads and decodes the useful fields of the NIC's EEPROM.
+    pub fn eeprom_info(&self) -> Result<EepromInfo, E1000Error> {
+        let image = self.eeprom_dump()?;
+        Ok(EepromInfo::decode(&image, self.port))
+    }
+
+    /// Reads from the EEPROM at address `addr`.
+    fn eeprom_read(&self, addr: u16) -> Result<u16, E1000Error> {
+        self.eeprom
+            .as_ref()
+            .ok_or(E1000Error::MissingEeprom)?
+            .read(addr)
+    }
+
+    /// Writes `words` to the EEPROM starting at address `addr`, and updates its checksum.
+    ///
+    /// This permanently changes the configuration of the NIC, including its MAC address. It must
+    /// only be reachable by privileged users. The changes take effect at the next reset.
+    pub fn eeprom_write(&mut self, addr: u16, words: &[u16]) -> Result<(), E1000Error> {
+        self.eeprom
+            .as_ref()
+            .ok_or(E1000Error::MissingEeprom)?
+            .write(addr, words)
+    }
+
+    /// Reads the MAC address of the NIC.
+    ///
+    /// The address is read from the EEPROM if present. Otherwise, or if the stored address is
+    /// invalid, `preloaded` is used, which is the content of the first Receive Address register
+    /// as preloaded by the firmware before the reset. If no valid address can be found, a random
+    /// locally administered address is used.
+    fn read_mac(&mut self, preloaded: Option<MAC>) {
+        let mac = if self.eeprom.is_some() {
+            self.read_eeprom_mac()
+                .map_err(|e| kernel::println!("e1000: {}: cannot read MAC address: {e}", self.name))
+                .ok()
+        } else {
+            None
+        };
+        let mac = mac
+            .filter(is_valid_mac)
+            .or_else(|| preloaded.filter(is_valid_mac));
+
+        self.mac = match mac {
+            Some(mac) => mac,
+            None => {
+                kernel::println!(
+                    "e1000: {}: no valid MAC address found, using a random one",
+                    self.name
+                );
+                random_mac(self as *const _ as usize)
+            }
+        };
+    }
+
+    /// Reads the MAC address from the NIC's EEPROM.
+    ///
+    /// The ports of a multi-port controller share the same EEPROM, so the address of the second
+    /// port is derived from the stored one by toggling its least significant bit.
+    fn read_eeprom_mac(&self) -> Result<MAC, E1000Error> {
+        let mut mac = [0; 6];
+
+        let val = self.eeprom_read(0)?;
+        mac[0] = (val & 0xff) as u8;
+        mac[1] = ((val >> 8) & 0xff) as u8;
+
+        let val = self.eeprom_read(1)?;
+        mac[2] = (val & 0xff) as u8;
+        mac[3] = ((val >> 8) & 0xff) as u8;
+
+        let val = self.eeprom_read(2)?;
+        mac[4] = (val & 0xff) as u8;
+        mac[5] = ((val >> 8) & 0xff) as u8;
+
+        if self.port & 1 != 0 {
+            mac[5] ^= 1;
+        }
+
+        Ok(mac)
+    }
+
+    /// Reads the MAC address from the first Receive Address register.
+    ///
+    /// If the register does not hold a valid address, the function returns `None`.
+    fn read_ra_mac(&self) -> Option<MAC> {
+        let ral = self.read_command(REG_RAL0);
+        let rah = self.read_command(REG_RAH0);
+        if rah & RAH_AV == 0 {
+            return None;
+        }
+
+        let mut mac = [0; 6];
+        mac[..4].copy_from_slice(&ral.to_le_bytes());
+        mac[4..].copy_from_slice(&rah.to_le_bytes()[..2]);
+        Some(mac)
+    }
+
+    /// Writes `mac` to the Receive Address register with index `index`. If `mac` is `None`, the
+    /// entry is invalidated.
+    ///
+    /// The entry is invalidated while being written so that the receiver never matches against a
+    /// partially written address.
+    fn write_ra(&self, index: u16, mac: Option<&MAC>) {
+        let ral_reg = REG_RAL0 + index * 8;
+        let rah_reg = REG_RAH0 + index * 8;
+
+        self.write_command(rah_reg, 0);
+        let Some(mac) = mac else {
+            self.write_command(ral_reg, 0);
+            return;
+        };
+        let ral = u32::from_le_bytes([mac[0], mac[1], mac[2], mac[3]]);
+        let rah = u32::from_le_bytes([mac[4], mac[5], 0, 0]);
+        self.write_command(ral_reg, ral);
+        self.write_command(rah_reg, rah | RAH_AV);
+    }
+
+    /// Adds a secondary unicast address to the interface.
+    ///
+    /// Addresses are reference counted: an address added several times must be removed as many
+    /// times.
+    ///
+    /// If the address is multicast or all zeros, the function returns an error.
+    pub fn add_unicast(&mut self, mac: MAC) -> Result<(), E1000Error> {
+        if self.removed {
+            return Err(E1000Error::Removed);
+        }
+        if !is_valid_mac(&mac) {
+            return Err(E1000Error::InvalidArgument);
+        }
+
+        if let Some((_, count)) = self.unicast.iter_mut().find(|(m, _)| *m == mac) {
+            *count += 1;
+            return Ok(());
+        }
+        self.unicast.push((mac, 1)).map_err(|_| E1000Error::Alloc)?;
+        self.update_unicast();
+        Ok(())
+    }
+
+    /// Removes a secondary unicast address from the interface.
+    ///
+    /// If the address has not been added, the function returns an error.
+    pub fn remove_unicast(&mut self, mac: MAC) -> Result<(), E1000Error> {
+        if self.removed {
+            return Err(E1000Error::Removed);
+        }
+
+        let i = self
+            .unicast
+            .iter()
+            .position(|(m, _)| *m == mac)
+            .ok_or(E1000Error::InvalidArgument)?;
+        self.unicast[i].1 -= 1;
+        if self.unicast[i].1 == 0 {
+            self.unicast.remove(i);
+            self.update_unicast();
+        }
+        Ok(())
+    }
+
+    /// Programs the secondary unicast addresses into the Receive Address registers following the
+    /// first one, or enables unicast promiscuous mode if there are too many of them.
+    fn update_unicast(&self) {
+        if self.unicast.len() < RA_COUNT as usize {
+            let mut addrs = self.unicast.iter().map(|(mac, _)| mac);
+            for i in 1..RA_COUNT {
+                self.write_ra(i, addrs.next());
+            }
+        }
+        self.update_rx_filter();
+    }
+
+    /// Changes the MAC address of the NIC.
+    ///
+    /// The receiver is paused while the address is being changed, so that no packet is filtered
+    /// against a mix of the old and new addresses.
+    ///
+    /// If the address is multicast or all zeros, the function returns an error.
+    pub fn set_mac(&mut self, mac: MAC) -> Result<(), E1000Error> {
+        if self.removed {
+            return Err(E1000Error::Removed);
+        }
+        if !is_valid_mac(&mac) {
+            return Err(E1000Error::InvalidArgument);
+        }
+
+        let rctl = self.read_command(REG_RCTL);
+        self.write_command(REG_RCTL, rctl & !RCTL_EN);
+        self.mac = mac;
+        self.write_ra(0, Some(&self.mac));
+        self.write_command(REG_RCTL, rctl);
+        Ok(())
+    }
+
+    /// Initializes transmit and receive descriptors.
+    fn init_desc(&mut self) -> Result<(), Errno> {
+        // Set interrupts mask
+        self.write_command(REG_IMS, INT_MASK);
+
+        // Init receive ring buffer
+        self.alloc_rx_buffs(rx_buff_size(self.mtu))?;
+        self.setup_rx_ring();
+
+        // Set receive flags
+        let flags = RCTL_EN | self.rx_filter_flags() | self.rx_size_flags();
+        self.write_command(REG_RCTL, flags);
+
+        // Init transmit ring buffer
+        self.alloc_tx_buffs()?;
+
+        self.setup_tx_ring();
 
         // Set transmit flags
         let retry_count = 0xf;
@@ -420,12 +1799,11 @@ impl NIC {
 
 impl net::Interface for NIC {
     fn get_name(&self) -> &[u8] {
-        b"eth"
+        self.name.as_bytes()
     }
 
     fn is_up(&self) -> bool {
-        // TODO
-        todo!();
+        !self.removed && self.admin_up && self.link().is_some()
     }
 
     fn get_mac(&self) -> &MAC {
@@ -438,142 +1816,231 @@ impl net::Interface for NIC {
     }
 
     fn read(&mut self, buff: &mut [u8]) -> Result<(), Errno> {
-        let mut i = 0;
-        let mut prev_cursor = None;
-
-        while i < buff.len() {
-            let desc = unsafe { &mut *self.tx_descs.add(self.rx_cur) };
-            if desc.status & RX_STA_DD == 0 {
-                break;
+        // The interface cannot report the length and metadata of the frame, use `receive` for that.
+        // If no frame has been received, `EAGAIN` is returned
+        loop {
+            let meta = self.receive(buff)?;
+            // Frames kept in store bad packets mode cannot be marked as bad here, so only
+            // `receive` returns them
+            if !meta.is_bad() {
+                return Ok(());
             }
+        }
+    }
 
-            let addr = memory::kern_to_virt(desc.addr as *const u8);
-            let len = min(buff.len() - i, desc.length as usize);
-            let slice = unsafe { slice::from_raw_parts(addr, len) };
-            buff[i..(i + len)].copy_from_slice(slice);
-
-            desc.status = 0;
-
-            i += len;
+    fn write(&mut self, buff: &BuffList<'_>) -> Result<(), Errno> {
+        self.transmit(buff, None)
+    }
+}
 
-            prev_cursor = Some(self.rx_cur);
-            self.rx_cur = (self.rx_cur + 1) % RX_DESC_COUNT;
+impl NIC {
+    /// Receives one frame into `buff` and returns its metadata.
+    ///
+    /// Frames spread over several descriptors are reassembled. If no complete frame has been
+    /// received, the function returns [`E1000Error::NoFrame`]. If the frame does not fit in `buff`
+    /// or is larger than the maximum frame size, it is dropped and the function returns an error.
+    ///
+    /// Frames with reception errors are counted and dropped, unless the store bad packets mode is
+    /// enabled. In that case, they are returned with their errors in the metadata.
+    pub fn receive(&mut self, buff: &mut [u8]) -> Result<RxMeta, E1000Error> {
+        if self.removed {
+            return Err(E1000Error::Removed);
         }
 
-        if let Some(prev_cursor) = prev_cursor {
-            self.write_command(REG_RDT, prev_cursor as _);
+        loop {
+            if let Some(meta) = self.receive_frame(buff)? {
+                return Ok(meta);
+            }
         }
-
-        Ok(())
     }
 
-    fn write(&mut self, buff: &BuffList<'_>) -> Result<(), Errno> {
-        // The function takes a set of input buffers and has to write them onto another set of
-        // buffers (the descriptors in use in the device's ring buffer) which may have a different
-        // size.
-        //
-        // To do so, it uses a loop which iterates on both buffers and copies data at each
-        // iteration.
-        //
-        // The length of the copy is the smallest length of remaining data in both buffer, to make
-        // sure there is no overflow.
-        //
-        // At each iteration, at least one of both buffers will be exhausted, which means at least
-        // the complexity is at least `O(min(a, b))` and at most `O(max(a, b))`, where `a` and `b`
-        // are the number of input buffers and the number of descriptors.
-        //
-        // If the next descriptors are busy, the function must wait until it becomes available by
-        // waiting for an interruption.
-
-        let mut src_iter = buff.iter();
-        let descriptors = unsafe { slice::from_raw_parts_mut(self.tx_descs, TX_DESC_COUNT) };
-
-        // get the next descriptor and wait if necessary
-        fn next_desc(s: &mut NIC, descriptors: &[TXDesc]) {
-            s.tx_cur = (s.tx_cur + 1) % TX_DESC_COUNT;
-            let desc = &descriptors[s.tx_cur];
-
-            // if the descriptor is busy, wait for it to become available again
-            loop {
-                let status = unsafe { ptr::read(&desc.status) };
-                if status & RX_STA_DD == 0 {
-                    break;
-                }
+    /// Receives the next frame into `buff` and returns its metadata.
+    ///
+    /// If the frame has been dropped because of reception errors, the function returns `None`.
+    fn receive_frame(&mut self, buff: &mut [u8]) -> Result<Option<RxMeta>, E1000Error> {
+        // Find the descriptors holding the frame, up to the one with EOP set
+        let descs = self.rx_descs();
+        let mut count = 0;
+        let mut len = 0;
+        let status = loop {
+            if count == self.rx_count - 1 {
+                // The NIC holds every descriptor it can use, so the frame cannot end
+                break 0;
+            }
+            let desc = unsafe { descs.add((self.rx_cur + count) % self.rx_count) };
+            let status = desc_read!(desc, status);
+            if status & RX_STA_DD == 0 {
+                return Err(E1000Error::NoFrame);
+            }
+            // The other fields are valid only once DD is set
+            rmb();
 
-                // TODO wait for transmit interrupt
-                todo!()
+            len += desc_read!(desc, length) as usize;
+            count += 1;
+            if status & RX_STA_EOP != 0 {
+                break status;
             }
-        }
+        };
 
-        let mut buff = src_iter.next();
-        let mut buff_off = 0;
-        // if the buffer is empty, do nothing
-        if buff.is_none() {
-            return Ok(());
+        // The metadata is reported in the last descriptor of the frame
+        let last = unsafe { descs.add((self.rx_cur + count - 1) % self.rx_count) };
+        let meta = RxMeta::decode(
+            len,
+            status,
+            desc_read!(last, errors),
+            desc_read!(last, checksum),
+            desc_read!(last, special),
+        );
+        if meta.is_bad() {
+            self.rx_errors.count(meta.errors);
         }
 
-        while let Some(ref src) = buff {
-            let desc = &mut descriptors[self.tx_cur];
-            let desc_len = desc.length as usize;
-            // if the descriptor is full, go to next
-            if desc_len >= TX_BUFF_SIZE {
-                next_desc(self, descriptors);
-                continue;
+        let res = if meta.is_bad() && !self.store_bad {
+            Ok(None)
+        } else if status & RX_STA_EOP == 0 || len > max_frame_len(self.mtu) {
+            Err(E1000Error::Oversize)
+        } else if len > buff.len() {
+            Err(E1000Error::Truncated)
+        } else {
+            let mut off = 0;
+            for i in 0..count {
+                let cur = (self.rx_cur + i) % self.rx_count;
+                let desc = unsafe { descs.add(cur) };
+                let desc_len = desc_read!(desc, length) as usize;
+                let src = unsafe { slice::from_raw_parts(self.rx_buff(cur), desc_len) };
+                buff[off..(off + desc_len)].copy_from_slice(src);
+                off += desc_len;
             }
+            Ok(Some(meta))
+        };
 
-            let dst = unsafe { slice::from_raw_parts_mut(desc.addr as *mut u8, TX_BUFF_SIZE) };
+        // Give the descriptors back to the NIC
+        for i in 0..count {
+            let desc = unsafe { descs.add((self.rx_cur + i) % self.rx_count) };
+            desc_write!(desc, status, 0);
+        }
+        let last = (self.rx_cur + count - 1) % self.rx_count;
+        self.rx_cur = (self.rx_cur + count) % self.rx_count;
+        wmb();
+        self.write_command(REG_RDT, last as _);
+
+        res
+    }
 
-            // copy data
-            let copy_len = min(src.len() - buff_off, TX_BUFF_SIZE - desc_len);
-            dst[desc_len..].copy_from_slice(&src[buff_off..(buff_off + copy_len)]);
-            buff_off += copy_len;
+    /// Transmits the packet in `buff` with the 802.1Q tag `vlan` inserted by the NIC.
+    ///
+    /// The NIC inserts tags only once a VLAN has been accepted with [`Self::add_vlan`]. Otherwise,
+    /// the function returns an error.
+    pub fn write_tagged(&mut self, buff: &BuffList<'_>, vlan: u16) -> Result<(), Errno> {
+        if !self.has_vlans() {
+            return Err(errno!(EINVAL));
+        }
+        self.transmit(buff, Some(vlan))
+    }
 
-            desc.length = (desc_len + copy_len) as _;
-            desc.cmd = TX_CMD_RS;
-            desc.status = 0;
+    /// Transmits the packet in `buff`, inserting the 802.1Q tag `vlan` if not `None`.
+    ///
+    /// If the packet is larger than the MTU, the function returns an error.
+    fn transmit(&mut self, buff: &BuffList<'_>, vlan: Option<u16>) -> Result<(), Errno> {
+        if self.removed {
+            return Err(errno!(ENODEV));
+        }
+        // The tag inserted by the NIC does not count in the MTU
+        if buff.len() > self.mtu + ETH_HEADER_LEN {
+            return Err(errno!(EMSGSIZE));
+        }
 
-            // get next buffer if the current is exhausted
-            if buff_off >= src.len() {
-                buff = src_iter.next();
-                buff_off = 0;
+        let total = buff.len();
+        if total == 0 {
+            return Ok(());
+        }
+        // One descriptor is always left unused, since a full ring cannot be told apart from an
+        // empty one
+        let count = math::ceil_div(total, TX_BUFF_SIZE);
+        if count >= self.tx_count {
+            return Err(errno!(EMSGSIZE));
+        }
+        self.wait_tx_descs(count)?;
+
+        // Copy the packet to the buffers of as many descriptors as necessary
+        let descs = self.tx_descs();
+        let mut cur = self.tx_cur;
+        let mut desc_len = 0;
+        let mut copied = 0;
+        for src in buff.iter() {
+            let mut off = 0;
+            while off < src.len() {
+                let copy_len = min(src.len() - off, TX_BUFF_SIZE - desc_len);
+                unsafe {
+                    let dst = self.tx_buff(cur).add(desc_len);
+                    ptr::copy_nonoverlapping(src[off..].as_ptr(), dst, copy_len);
+                }
+                off += copy_len;
+                desc_len += copy_len;
+                copied += copy_len;
+
+                let last = copied == total;
+                if desc_len < TX_BUFF_SIZE && !last {
+                    continue;
+                }
+                let desc = unsafe { descs.add(cur) };
+                let mut cmd = TX_CMD_RS;
+                let mut special = 0;
+                if last {
+                    cmd |= TX_CMD_EOP | TX_CMD_IFCS;
+                    // The tag is taken from the last descriptor of the packet
+                    if let Some(vlan) = vlan {
+                        cmd |= TX_CMD_VLE;
+                        special = vlan;
+                    }
+                }
+                let new = TXDesc {
+                    addr: desc_read!(desc, addr),
+                    length: desc_len as _,
+                    cmd,
+                    special,
+                    ..Default::default()
+                };
+                unsafe {
+                    ptr::write_volatile(desc, new);
+                }
+
+                cur = (cur + 1) % self.tx_count;
+                desc_len = 0;
             }
         }
 
-        // flush descriptors
-        let desc = &mut descriptors[self.tx_cur];
-        desc.cmd |= TX_CMD_EOP | TX_CMD_IFCS;
-        self.write_command(REG_TDT, self.tx_cur as _);
+        // Hand the descriptors over to the NIC
+        self.tx_cur = cur;
+        wmb();
+        self.write_command(REG_TDT, cur as _);
 
         Ok(())
     }
+
+    /// Waits until the `count` descriptors starting at the transmit cursor have been processed by
+    /// the NIC.
+    ///
+    /// If they are still busy after a while, the function returns an error.
+    fn wait_tx_descs(&self, count: usize) -> Result<(), Errno> {
+        let descs = self.tx_descs();
+        // TODO wait for the transmit interrupt instead of polling
+        let free = (0..TX_DRAIN_ATTEMPTS).any(|_| {
+            (0..count).all(|i| {
+                let desc = unsafe { descs.add((self.tx_cur + i) % self.tx_count) };
+                desc_read!(desc, status) & TX_STA_DD != 0
+            })
+        });
+        if !free {
+            return Err(errno!(EAGAIN));
+        }
+        rmb();
+        Ok(())
+    }
 }
 
 impl Drop for NIC {
     fn drop(&mut self) {
-        let rx_buffs = unsafe { (*self.rx_descs).addr } as _;
-        let rx_buffs_order = buddy::get_order(math::ceil_div(
-            RX_DESC_COUNT * RX_BUFF_SIZE,
-            memory::PAGE_SIZE,
-        ));
-        buddy::free_kernel(rx_buffs, rx_buffs_order);
-
-        let rx_order = buddy::get_order(math::ceil_div(
-            RX_DESC_COUNT * size_of::<RXDesc>(),
-            memory::PAGE_SIZE,
-        ));
-        buddy::free_kernel(self.rx_descs as _, rx_order);
-
-        let tx_buffs = unsafe { (*self.tx_descs).addr } as _;
-        let tx_buffs_order = buddy::get_order(math::ceil_div(
-            TX_DESC_COUNT * TX_BUFF_SIZE,
-            memory::PAGE_SIZE,
-        ));
-        buddy::free_kernel(tx_buffs, tx_buffs_order);
-
-        let tx_order = buddy::get_order(math::ceil_div(
-            TX_DESC_COUNT * size_of::<TXDesc>(),
-            memory::PAGE_SIZE,
-        ));
-        buddy::free_kernel(self.tx_descs as _, tx_order);
+        self.shutdown();
     }
 }